@@ -0,0 +1,135 @@
+use owo_colors::OwoColorize;
+use owo_colors::Stream::Stderr;
+use serde::Deserialize;
+use std::fmt::Display;
+
+/// The tag Claude wraps its per-hunk attribution in, at the end of its final reply.
+const TAG: &str = "hunks";
+
+/// Instructions for `--attribution`, appended to the system prompt.
+pub fn prompt() -> String {
+    format!(
+        "After resolving the conflict, end your reply with a `<{TAG}>` block containing a JSON \
+         array with one object per conflicting region, in file order, like this:\n\n\
+         <{TAG}>\n\
+         [{{\"lines\": \"12-18\", \"took\": \"both\", \"note\": \"kept the new argument from \
+         ours and the rename from theirs\"}}]\n\
+         </{TAG}>\n\n\
+         `lines` is the range of lines in the resolved file, `took` is one of `ours` (left), \
+         `theirs` (right), `both`, or `neither`, and `note` briefly says why."
+    )
+}
+
+/// Which side of the conflict a resolved region came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Ours,
+    Theirs,
+    Both,
+    Neither,
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Side::Ours => write!(f, "ours"),
+            Side::Theirs => write!(f, "theirs"),
+            Side::Both => write!(f, "both"),
+            Side::Neither => write!(f, "neither"),
+        }
+    }
+}
+
+/// Claude's account of how it resolved one conflicting region.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct HunkAttribution {
+    /// Lines in the resolved file, like `12-18`.
+    pub lines: String,
+    pub took: Side,
+    #[serde(default)]
+    pub note: String,
+}
+
+/// Parse the last `<hunks>` block in Claude's reply.
+///
+/// Returns `None` if there's no block or it isn't valid, since the attribution is only
+/// informational.
+pub fn parse(reply: &str) -> Option<Vec<HunkAttribution>> {
+    let open = format!("<{TAG}>");
+    let close = format!("</{TAG}>");
+    let start = reply.rfind(&open)? + open.len();
+    let end = start + reply[start..].find(&close)?;
+    match serde_json::from_str(&reply[start..end]) {
+        Ok(hunks) => Some(hunks),
+        Err(err) => {
+            tracing::debug!("Failed to parse Claude's hunk attribution: {err}");
+            None
+        }
+    }
+}
+
+/// The attribution summary shown after a merge.
+pub struct Summary<'a>(pub &'a [HunkAttribution]);
+
+impl Display for Summary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            "Resolved hunks:".if_supports_color(Stderr, |text| text.bold())
+        )?;
+        for hunk in self.0 {
+            write!(
+                f,
+                "\n  lines {}: {}",
+                hunk.lines,
+                hunk.took.if_supports_color(Stderr, |text| text.cyan())
+            )?;
+            if !hunk.note.is_empty() {
+                write!(f, " ({})", hunk.note)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::expect;
+
+    #[test]
+    fn parse_attribution_block() {
+        owo_colors::set_override(false);
+        let reply = r#"I kept both changes to `parse` and took their version of the imports.
+
+<hunks>
+[
+  {"lines": "3-5", "took": "theirs", "note": "their imports are a superset of ours"},
+  {"lines": "12-18", "took": "both"},
+  {"lines": "40", "took": "neither", "note": "both sides' versions were dead code"}
+]
+</hunks>"#;
+        let hunks = parse(reply).unwrap();
+        assert_eq!(hunks.len(), 3);
+        assert_eq!(hunks[1].took, Side::Both);
+        expect![[r#"
+            Resolved hunks:
+              lines 3-5: theirs (their imports are a superset of ours)
+              lines 12-18: both
+              lines 40: neither (both sides' versions were dead code)"#]]
+        .assert_eq(&Summary(&hunks).to_string());
+    }
+
+    #[test]
+    fn parse_missing_or_invalid() {
+        assert_eq!(parse("Merged both changes."), None);
+        assert_eq!(parse("<hunks>\n[{\"lines\": \"1\"}]\n</hunks>"), None);
+        assert_eq!(
+            parse("<hunks>[{\"lines\": \"1\", \"took\": \"mine\"}]</hunks>"),
+            None
+        );
+        assert_eq!(parse("<hunks>[]"), None);
+    }
+}
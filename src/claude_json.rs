@@ -1,69 +1,288 @@
+use crate::logging;
+use crate::pricing::InputCost;
+use crate::pricing::ModelPricing;
+use crate::pricing::TokenUsage;
 use owo_colors::OwoColorize;
+use owo_colors::Stream::Stderr;
 use serde::Deserialize;
+use serde::Serialize;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fmt::Display;
-use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
 use std::time::Duration;
 
+/// How Claude's events are rendered.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DisplayOptions {
+    /// Print Claude's messages as-is instead of rendering them as Markdown.
+    pub plain: bool,
+    /// Show Claude's extended thinking.
+    pub show_thinking: bool,
+    /// Show a preview of the output of Claude's tool calls.
+    pub show_tool_results: bool,
+    /// Only show the final result, not Claude's messages and tool calls.
+    pub quiet: bool,
+    /// Wrap rendered Markdown at this many columns instead of the terminal width.
+    pub wrap_width: Option<usize>,
+    /// The budget to compare Claude's final cost against.
+    pub max_cost_usd: Option<f64>,
+    /// Show up to this many removed and added lines for each `Edit`; 0 to hide edits.
+    pub diff_lines: usize,
+}
+
 pub struct ClaudeEventWriter {
     /// Temp directory prefixes to replace with `$TMPDIR`, longest first.
     temp_dirs: Vec<String>,
+    /// Configured model rates, for breaking down the cost of cached input.
+    pricing: BTreeMap<String, ModelPricing>,
     /// Whether we've written any output yet (for stripping leading newlines).
     has_output: AtomicBool,
+    /// Lines that didn't parse as any event we know about.
+    skipped: AtomicUsize,
+    options: DisplayOptions,
 }
 
 impl ClaudeEventWriter {
-    pub fn new() -> miette::Result<Self> {
-        let raw = std::env::temp_dir();
-        let mut temp_dirs = Vec::new();
-
-        // Add canonicalized path (e.g. /private/tmp on macOS).
-        if let Ok(canonical) = raw.canonicalize() {
-            let s = canonical.to_string_lossy().into_owned();
-            temp_dirs.push(s);
-        }
-
-        // Add raw path if it differs from canonical.
-        let raw_s = raw.to_string_lossy().into_owned();
-        if !temp_dirs.contains(&raw_s) {
-            temp_dirs.push(raw_s);
-        }
-
-        // Longest first so we don't partially replace a longer prefix.
-        temp_dirs.sort_by_key(|b| std::cmp::Reverse(b.len()));
-
+    pub fn new(options: DisplayOptions) -> miette::Result<Self> {
         Ok(Self {
-            temp_dirs,
+            temp_dirs: temp_dir_prefixes(&std::env::temp_dir(), true, &[]),
+            pricing: BTreeMap::new(),
             has_output: AtomicBool::new(false),
+            skipped: AtomicUsize::new(0),
+            options,
         })
     }
 
+    /// Replace the temp directory prefixes: `canonicalize` controls whether the temp directory's
+    /// symlinks are resolved, and `extra` adds more prefixes to replace.
+    pub fn with_temp_dirs(mut self, canonicalize: bool, extra: &[String]) -> Self {
+        self.temp_dirs = temp_dir_prefixes(&std::env::temp_dir(), canonicalize, extra);
+        self
+    }
+
+    pub fn with_pricing(mut self, pricing: BTreeMap<String, ModelPricing>) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
     pub fn display(&self, event: &str) -> Option<DisplayableEvent<'_>> {
         match serde_json::from_str::<ClaudeEvent>(event) {
             Ok(parsed) => Some(DisplayableEvent {
                 parsed,
                 temp_dirs: &self.temp_dirs,
+                pricing: &self.pricing,
                 has_output: &self.has_output,
+                options: self.options,
             }),
             Err(_) => {
                 tracing::debug!(event = %event, "Skipping Claude event");
+                self.skipped.fetch_add(1, Relaxed);
                 None
             }
         }
     }
+
+    /// How many lines we've skipped because they didn't parse, which usually means `claude`'s
+    /// output format has changed.
+    pub fn skipped_events(&self) -> usize {
+        self.skipped.load(Relaxed)
+    }
+}
+
+/// Cost and usage from a successful result event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultUsage {
+    pub total_cost_usd: f64,
+    /// Input, output, and cache tokens.
+    pub tokens: u64,
+    pub duration: Duration,
+}
+
+/// Parse a successful result event, such as a line from the summary log.
+pub fn parse_result_usage(line: &str) -> Option<ResultUsage> {
+    match serde_json::from_str::<ClaudeEvent>(line).ok()? {
+        ClaudeEvent::Result {
+            result: ClaudeResult::Success(success),
+        } => Some(ResultUsage {
+            total_cost_usd: success.total_cost_usd,
+            tokens: success.usage.input_tokens
+                + success.usage.output_tokens
+                + success.usage.cache_creation_input_tokens
+                + success.usage.cache_read_input_tokens,
+            duration: success.duration,
+        }),
+        _ => None,
+    }
+}
+
+/// Tokens used by one of Claude's messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageUsage {
+    pub id: String,
+    pub model: String,
+    pub usage: TokenUsage,
 }
 
 pub struct DisplayableEvent<'a> {
     parsed: ClaudeEvent,
     temp_dirs: &'a [String],
+    pricing: &'a BTreeMap<String, ModelPricing>,
     has_output: &'a AtomicBool,
+    options: DisplayOptions,
 }
 
 impl DisplayableEvent<'_> {
     pub fn is_result(&self) -> bool {
         matches!(self.parsed, ClaudeEvent::Result { .. })
     }
+
+    pub fn is_assistant(&self) -> bool {
+        matches!(self.parsed, ClaudeEvent::Assistant { .. })
+    }
+
+    /// Whether this is an assistant message with some text in it.
+    pub fn has_text(&self) -> bool {
+        self.content_blocks().any(|block| match block {
+            ContentBlock::Text { text } => !text.trim().is_empty(),
+            _ => false,
+        })
+    }
+
+    /// Paths that Claude is writing or editing in this event.
+    pub fn written_files(&self) -> impl Iterator<Item = &str> {
+        self.content_blocks().filter_map(|block| match block {
+            ContentBlock::ToolUse { name, input }
+                if matches!(name.as_str(), "Write" | "Edit" | "MultiEdit") =>
+            {
+                input.file_path.as_deref()
+            }
+            _ => None,
+        })
+    }
+
+    /// The tokens used by an assistant message so far.
+    ///
+    /// Claude sends a separate event for each content block of a message, so the same message
+    /// ID can show up more than once; the last usage reported for it is the total.
+    pub fn message_usage(&self) -> Option<MessageUsage> {
+        match &self.parsed {
+            ClaudeEvent::Assistant {
+                message:
+                    AssistantMessage {
+                        id: Some(id),
+                        model: Some(model),
+                        usage: Some(usage),
+                        ..
+                    },
+            } => Some(MessageUsage {
+                id: id.clone(),
+                model: model.clone(),
+                usage: usage.into(),
+            }),
+            _ => None,
+        }
+    }
+
+    fn content_blocks(&self) -> impl Iterator<Item = &ContentBlock> {
+        match &self.parsed {
+            ClaudeEvent::Assistant { message } => message.content.as_slice(),
+            _ => &[],
+        }
+        .iter()
+    }
+
+    /// The ID `claude` gave the session, which `claude --resume` takes to continue it.
+    pub fn session_id(&self) -> Option<&str> {
+        match &self.parsed {
+            ClaudeEvent::System { session_id } => session_id.as_deref(),
+            ClaudeEvent::Result {
+                result: ClaudeResult::Success(success),
+            } => success.session_id.as_deref(),
+            ClaudeEvent::Result {
+                result: ClaudeResult::Error(error),
+            } => error.session_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The total cost of the session so far, if this event reports it.
+    pub fn total_cost_usd(&self) -> Option<f64> {
+        match &self.parsed {
+            ClaudeEvent::Result {
+                result: ClaudeResult::Success(success),
+            } => Some(success.total_cost_usd),
+            ClaudeEvent::Result {
+                result: ClaudeResult::Error(error),
+            } => error.total_cost_usd,
+            _ => None,
+        }
+    }
+
+    /// Why Claude failed, if this is an error result event.
+    pub fn error_reason(&self) -> Option<String> {
+        match &self.parsed {
+            ClaudeEvent::Result {
+                result: ClaudeResult::Error(error),
+            } => Some(error.reason()),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a result event for an error that might not happen again, like the API
+    /// being overloaded, as opposed to one that would, like running out of turns.
+    pub fn is_transient_error(&self) -> bool {
+        match &self.parsed {
+            ClaudeEvent::Result {
+                result: ClaudeResult::Error(error),
+            } => {
+                error.subtype == "error_during_execution"
+                    || error.result.as_deref().is_some_and(is_transient_message)
+            }
+            // API errors are reported as "successful" results with `is_error` set.
+            ClaudeEvent::Result {
+                result: ClaudeResult::Success(success),
+            } => success.is_error && is_transient_message(&success.result),
+            _ => false,
+        }
+    }
+
+    /// How many web searches Claude made, if this is a successful result event.
+    pub fn web_search_requests(&self) -> u64 {
+        match &self.parsed {
+            ClaudeEvent::Result {
+                result: ClaudeResult::Success(success),
+            } => success
+                .model_usage
+                .values()
+                .map(|usage| usage.web_search_requests)
+                .sum(),
+            _ => 0,
+        }
+    }
+
+    /// A successful result event's cost, durations, and usage as a JSON object, for
+    /// `--output-format json`.
+    pub fn result_json(&self) -> Option<String> {
+        match &self.parsed {
+            ClaudeEvent::Result {
+                result: ClaudeResult::Success(success),
+            } => Some(serde_json::to_string(success).expect("result events always serialize")),
+            _ => None,
+        }
+    }
+
+    /// The final message from a successful result event.
+    pub fn result_text(&self) -> Option<&str> {
+        match &self.parsed {
+            ClaudeEvent::Result {
+                result: ClaudeResult::Success(success),
+            } => Some(&success.result),
+            _ => None,
+        }
+    }
 }
 
 impl Display for DisplayableEvent<'_> {
@@ -71,7 +290,8 @@ impl Display for DisplayableEvent<'_> {
         write!(
             f,
             "{}",
-            self.parsed.display(self.has_output, self.temp_dirs)
+            self.parsed
+                .display(self.has_output, self.temp_dirs, self.pricing, self.options)
         )
     }
 }
@@ -79,9 +299,18 @@ impl Display for DisplayableEvent<'_> {
 #[derive(Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum ClaudeEvent {
+    /// Session metadata, like the `init` event at the start; not shown.
+    System {
+        #[serde(default)]
+        session_id: Option<String>,
+    },
     Assistant {
         message: AssistantMessage,
     },
+    /// Sent on Claude's behalf with the results of its tool calls.
+    User {
+        message: UserMessage,
+    },
     Result {
         #[serde(flatten)]
         result: ClaudeResult,
@@ -93,11 +322,15 @@ impl ClaudeEvent {
         &'a self,
         has_output: &'a AtomicBool,
         temp_dirs: &'a [String],
+        pricing: &'a BTreeMap<String, ModelPricing>,
+        options: DisplayOptions,
     ) -> ClaudeEventDisplay<'a> {
         ClaudeEventDisplay {
             event: self,
             has_output,
             temp_dirs,
+            pricing,
+            options,
         }
     }
 }
@@ -106,6 +339,8 @@ struct ClaudeEventDisplay<'a> {
     event: &'a ClaudeEvent,
     has_output: &'a AtomicBool,
     temp_dirs: &'a [String],
+    pricing: &'a BTreeMap<String, ModelPricing>,
+    options: DisplayOptions,
 }
 
 impl ClaudeEventDisplay<'_> {
@@ -120,9 +355,83 @@ impl ClaudeEventDisplay<'_> {
     }
 }
 
+impl ClaudeEventDisplay<'_> {
+    /// Write an `Edit` as removed and added lines, each side cut off at `diff_lines`.
+    fn write_edit(&self, f: &mut std::fmt::Formatter<'_>, edit: &EditInput) -> std::fmt::Result {
+        let limit = self.options.diff_lines;
+        for (prefix, text) in [("-", &edit.old_string), ("+", &edit.new_string)] {
+            let text = self.scrub(text);
+            let lines = text.lines().collect::<Vec<_>>();
+            for line in lines.iter().take(limit) {
+                let line = format!("  {prefix} {line}");
+                if prefix == "-" {
+                    writeln!(f, "{}", line.if_supports_color(Stderr, |text| text.red()))?;
+                } else {
+                    writeln!(f, "{}", line.if_supports_color(Stderr, |text| text.green()))?;
+                }
+            }
+            if lines.len() > limit {
+                writeln!(
+                    f,
+                    "{}",
+                    format!("  {prefix} … {} more lines", lines.len() - limit)
+                        .if_supports_color(Stderr, |text| text.dimmed())
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ClaudeEventDisplay<'_> {
+    /// Write the first `max_lines` lines of a tool call's output, each cut off at a reasonable
+    /// width.
+    fn write_tool_result(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        text: &str,
+        is_error: bool,
+        max_lines: usize,
+    ) -> std::fmt::Result {
+        const MAX_WIDTH: usize = 120;
+        let text = self.scrub(text.trim_matches('\n'));
+        let lines = text.lines().collect::<Vec<_>>();
+        if lines.is_empty() {
+            return Ok(());
+        }
+        for line in lines.iter().take(max_lines) {
+            let line = match line.char_indices().nth(MAX_WIDTH) {
+                Some((end, _)) => format!("  │ {}…", &line[..end]),
+                None => format!("  │ {line}"),
+            };
+            if is_error {
+                writeln!(f, "{}", line.if_supports_color(Stderr, |text| text.red()))?;
+            } else {
+                writeln!(
+                    f,
+                    "{}",
+                    line.if_supports_color(Stderr, |text| text.dimmed())
+                )?;
+            }
+        }
+        if lines.len() > max_lines {
+            writeln!(
+                f,
+                "{}",
+                format!("  │ … {} more lines", lines.len() - max_lines)
+                    .if_supports_color(Stderr, |text| text.dimmed())
+            )?;
+        }
+        self.has_output.store(true, Relaxed);
+        Ok(())
+    }
+}
+
 impl Display for ClaudeEventDisplay<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.event {
+            ClaudeEvent::System { .. } => {}
+            ClaudeEvent::Assistant { .. } if self.options.quiet => {}
             ClaudeEvent::Assistant { message } => {
                 for block in &message.content {
                     match block {
@@ -134,24 +443,74 @@ impl Display for ClaudeEventDisplay<'_> {
                             };
                             if !text.is_empty() {
                                 let text = self.scrub(text);
-                                write!(f, "{}", termimad::term_text(&text))?;
+                                if self.options.plain {
+                                    write!(f, "{text}")?;
+                                } else {
+                                    write!(
+                                        f,
+                                        "{}",
+                                        render_markdown(&text, self.options.wrap_width)
+                                    )?;
+                                }
+                                self.has_output.store(true, Relaxed);
+                            }
+                        }
+                        ContentBlock::Thinking { thinking } => {
+                            let thinking = thinking.trim();
+                            if self.options.show_thinking && !thinking.is_empty() {
+                                let thinking = self.scrub(thinking);
+                                writeln!(
+                                    f,
+                                    "{}",
+                                    thinking.if_supports_color(Stderr, |text| text
+                                        .dimmed()
+                                        .italic()
+                                        .to_string())
+                                )?;
                                 self.has_output.store(true, Relaxed);
                             }
                         }
                         ContentBlock::ToolUse { name, input } => {
-                            match name.as_str() {
-                                "Read" | "Write" | "Edit" => {
-                                    let path =
-                                        self.scrub(input.file_path.as_deref().unwrap_or("?"));
-                                    writeln!(f, "{}", format!("> {name} {path}").dimmed())?;
+                            match input.summary(name) {
+                                Some(summary) => {
+                                    let summary = self.scrub(&summary);
+                                    writeln!(
+                                        f,
+                                        "{}",
+                                        format!("> {name} {summary}")
+                                            .if_supports_color(Stderr, |text| text.dimmed())
+                                    )?;
                                 }
-                                _ => {
+                                None => {
                                     writeln!(f, "> {name}")?;
                                 }
                             }
+                            if self.options.diff_lines > 0 {
+                                for edit in input.edits() {
+                                    self.write_edit(f, edit)?;
+                                }
+                            }
                             self.has_output.store(true, Relaxed);
                         }
-                        ContentBlock::Unknown => {}
+                        ContentBlock::ToolResult { .. } | ContentBlock::Unknown => {}
+                    }
+                }
+            }
+            ClaudeEvent::User { .. } if self.options.quiet => {}
+            ClaudeEvent::User { message } => {
+                for block in &message.content {
+                    if let ContentBlock::ToolResult {
+                        content: Some(content),
+                        is_error,
+                    } = block
+                    {
+                        if self.options.show_tool_results {
+                            self.write_tool_result(f, &content.text(), *is_error, 5)?;
+                        } else if *is_error {
+                            // Failed tool calls are worth knowing about even without
+                            // `show_tool_results`, but keep them short.
+                            self.write_tool_result(f, &content.text(), true, 1)?;
+                        }
                     }
                 }
             }
@@ -159,6 +518,51 @@ impl Display for ClaudeEventDisplay<'_> {
                 result: ClaudeResult::Success(success),
             } => {
                 writeln!(f, "{success}")?;
+                if let Some(cost) = success.input_cost_usd(self.pricing) {
+                    let savings = cost.caching_savings();
+                    let savings = if savings >= 0.0 {
+                        format!("caching saved {}", Dollars(savings))
+                    } else {
+                        format!("caching cost {} extra", Dollars(-savings))
+                    };
+                    writeln!(
+                        f,
+                        "{}",
+                        format!(
+                            "Input cost: {} uncached, {} cache writes, {} cache reads ({savings})",
+                            Dollars(cost.input),
+                            Dollars(cost.cache_write),
+                            Dollars(cost.cache_read),
+                        )
+                        .if_supports_color(Stderr, |text| text.dimmed())
+                    )?;
+                }
+                if let Some(budget) = self.options.max_cost_usd {
+                    let line = format!(
+                        "Budget: {} of {}",
+                        Dollars(success.total_cost_usd),
+                        Dollars(budget)
+                    );
+                    if success.total_cost_usd > budget {
+                        writeln!(
+                            f,
+                            "{}",
+                            line.if_supports_color(Stderr, |text| text.red().bold().to_string())
+                        )?;
+                    } else {
+                        writeln!(
+                            f,
+                            "{}",
+                            line.if_supports_color(Stderr, |text| text.dimmed())
+                        )?;
+                    }
+                }
+                self.has_output.store(true, Relaxed);
+            }
+            ClaudeEvent::Result {
+                result: ClaudeResult::Error(error),
+            } => {
+                writeln!(f, "{error}")?;
                 self.has_output.store(true, Relaxed);
             }
         }
@@ -168,6 +572,18 @@ impl Display for ClaudeEventDisplay<'_> {
 
 #[derive(Deserialize)]
 struct AssistantMessage {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    model: Option<String>,
+    #[serde(default)]
+    content: Vec<ContentBlock>,
+    #[serde(default)]
+    usage: Option<ClaudeUsage>,
+}
+
+#[derive(Deserialize)]
+struct UserMessage {
     #[serde(default)]
     content: Vec<ContentBlock>,
 }
@@ -178,32 +594,173 @@ enum ContentBlock {
     Text {
         text: String,
     },
+    Thinking {
+        thinking: String,
+    },
     ToolUse {
         name: String,
         #[serde(default)]
         input: ToolInput,
     },
+    ToolResult {
+        #[serde(default)]
+        content: Option<ToolResultContent>,
+        #[serde(default)]
+        is_error: bool,
+    },
     #[serde(other)]
     Unknown,
 }
 
+/// The output of a tool call: either text, or a list of blocks like text and images.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ToolResultContent {
+    Text(String),
+    Blocks(Vec<ToolResultBlock>),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ToolResultBlock {
+    Text {
+        text: String,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+impl ToolResultContent {
+    fn text(&self) -> Cow<'_, str> {
+        match self {
+            ToolResultContent::Text(text) => Cow::Borrowed(text),
+            ToolResultContent::Blocks(blocks) => Cow::Owned(
+                blocks
+                    .iter()
+                    .filter_map(|block| match block {
+                        ToolResultBlock::Text { text } => Some(text.as_str()),
+                        ToolResultBlock::Unknown => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+        }
+    }
+}
+
 #[derive(Default, Deserialize)]
 struct ToolInput {
+    /// For `Read`, `Write`, and `Edit`.
     file_path: Option<String>,
+    /// For `Bash`.
+    command: Option<String>,
+    /// For `Grep` and `Glob`.
+    pattern: Option<String>,
+    /// For `Grep` and `Glob`.
+    path: Option<String>,
+    /// For `Edit`.
+    #[serde(flatten)]
+    edit: Option<EditInput>,
+    /// For `MultiEdit`.
+    #[serde(default)]
+    edits: Vec<EditInput>,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
-#[serde(tag = "subtype", rename_all = "snake_case")]
+#[derive(Deserialize)]
+struct EditInput {
+    old_string: String,
+    new_string: String,
+}
+
+impl ToolInput {
+    fn edits(&self) -> impl Iterator<Item = &EditInput> {
+        self.edit.iter().chain(&self.edits)
+    }
+
+    /// A short description of what the tool call does, shown after the tool's name.
+    fn summary(&self, name: &str) -> Option<String> {
+        match name {
+            "Read" | "Write" | "Edit" | "MultiEdit" => {
+                Some(self.file_path.as_deref().unwrap_or("?").to_owned())
+            }
+            "Bash" => {
+                let command = self.command.as_deref()?.trim();
+                Some(match command.split_once('\n') {
+                    Some((first, _)) => format!("{first} …"),
+                    None => command.to_owned(),
+                })
+            }
+            "Grep" | "Glob" => {
+                let pattern = self.pattern.as_deref()?;
+                let pattern = if name == "Grep" {
+                    format!("\"{pattern}\"")
+                } else {
+                    pattern.to_owned()
+                };
+                Some(match &self.path {
+                    Some(path) => format!("{pattern} {path}"),
+                    None => pattern,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 enum ClaudeResult {
     Success(ClaudeSuccess),
+    /// Any of the `error_*` subtypes, like `error_max_turns` or `error_during_execution`.
+    Error(ClaudeError),
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+impl<'de> Deserialize<'de> for ClaudeResult {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        // Dispatch on `subtype` by hand so that error subtypes we don't know about yet still
+        // deserialize as errors.
+        let value = serde_json::Value::deserialize(d)?;
+        let result = if value.get("subtype").and_then(|subtype| subtype.as_str()) == Some("success")
+        {
+            serde_json::from_value(value).map(ClaudeResult::Success)
+        } else {
+            serde_json::from_value(value).map(ClaudeResult::Error)
+        };
+        result.map_err(serde::de::Error::custom)
+    }
+}
+
+/// Phrases in `claude`'s error messages for failures that are worth retrying.
+const TRANSIENT_ERRORS: &[&str] = &[
+    "overloaded",
+    "rate limit",
+    "rate_limit",
+    "timed out",
+    "connection error",
+    "internal server error",
+    "api error: 5",
+];
+
+fn is_transient_message(message: &str) -> bool {
+    let message = message.to_lowercase();
+    TRANSIENT_ERRORS
+        .iter()
+        .any(|transient| message.contains(transient))
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 struct ClaudeSuccess {
     is_error: bool,
-    #[serde(rename = "duration_ms", deserialize_with = "deserialize_millis")]
+    #[serde(
+        rename = "duration_ms",
+        deserialize_with = "deserialize_millis",
+        serialize_with = "serialize_millis"
+    )]
     duration: Duration,
-    #[serde(rename = "duration_api_ms", deserialize_with = "deserialize_millis")]
+    #[serde(
+        rename = "duration_api_ms",
+        deserialize_with = "deserialize_millis",
+        serialize_with = "serialize_millis"
+    )]
     api_duration: Duration,
     num_turns: u64,
     result: String,
@@ -212,12 +769,97 @@ struct ClaudeSuccess {
     // Why does this One field have a different naming format.
     #[serde(rename = "modelUsage")]
     model_usage: HashMap<String, ClaudeModelUsage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>,
 }
 
 fn deserialize_millis<'de, D: serde::Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
     u64::deserialize(d).map(Duration::from_millis)
 }
 
+fn serialize_millis<S: serde::Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u128(duration.as_millis())
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct ClaudeError {
+    subtype: String,
+    is_error: bool,
+    #[serde(rename = "duration_ms", deserialize_with = "deserialize_millis")]
+    duration: Duration,
+    #[serde(default)]
+    num_turns: Option<u64>,
+    #[serde(default)]
+    result: Option<String>,
+    #[serde(default)]
+    total_cost_usd: Option<f64>,
+    #[serde(default)]
+    session_id: Option<String>,
+}
+
+impl ClaudeError {
+    fn reason(&self) -> String {
+        match self.subtype.as_str() {
+            "error_max_turns" => "reached the maximum number of turns".to_owned(),
+            "error_during_execution" => "error during execution".to_owned(),
+            subtype => subtype.to_owned(),
+        }
+    }
+}
+
+impl Display for ClaudeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self.num_turns {
+                Some(turns) => format!(
+                    "Failed after {} and {turns} turns: {}",
+                    HumanTime(self.duration),
+                    self.reason()
+                ),
+                None => format!(
+                    "Failed after {}: {}",
+                    HumanTime(self.duration),
+                    self.reason()
+                ),
+            }
+            .if_supports_color(Stderr, |text| text.red().bold().to_string())
+        )?;
+
+        if let Some(result) = &self.result {
+            write!(
+                f,
+                "\n{}",
+                result.if_supports_color(Stderr, |text| text.red())
+            )?;
+        }
+
+        if let Some(cost) = self.total_cost_usd {
+            write!(
+                f,
+                "{}",
+                format!("\nTotal cost: {}", Dollars(cost))
+                    .if_supports_color(Stderr, |text| text.dimmed())
+            )?;
+        }
+
+        write_session_id(f, self.session_id.as_deref())
+    }
+}
+
+/// Show the session ID, so it can be passed to `--resume`.
+fn write_session_id(f: &mut std::fmt::Formatter<'_>, session_id: Option<&str>) -> std::fmt::Result {
+    match session_id {
+        Some(id) => write!(
+            f,
+            "{}",
+            format!("\nSession: {id}").if_supports_color(Stderr, |text| text.dimmed())
+        ),
+        None => Ok(()),
+    }
+}
+
 impl Display for ClaudeSuccess {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -241,22 +883,31 @@ impl Display for ClaudeSuccess {
                         }
                 ),
             )
-            .green()
-            .bold()
+            .if_supports_color(Stderr, |text| text.green().bold().to_string())
         )?;
 
         if !self.model_usage.is_empty() {
-            write!(f, "{}", "\nUsage by model:".dimmed())?;
+            write!(
+                f,
+                "{}",
+                "\nUsage by model:".if_supports_color(Stderr, |text| text.dimmed())
+            )?;
             for (name, usage) in &self.model_usage {
-                write!(f, "{}", format!("\n    {name}: {usage}").dimmed())?;
+                write!(
+                    f,
+                    "{}",
+                    format!("\n    {name}: {usage}")
+                        .if_supports_color(Stderr, |text| text.dimmed())
+                )?;
             }
         }
 
-        Ok(())
+        write_session_id(f, self.session_id.as_deref())
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 struct ClaudeUsage {
     input_tokens: u64,
     cache_creation_input_tokens: u64,
@@ -264,7 +915,34 @@ struct ClaudeUsage {
     output_tokens: u64,
 }
 
-#[derive(Debug, Clone, PartialEq, Deserialize)]
+impl ClaudeSuccess {
+    /// Break down the cost of each model's input tokens, or `None` if we don't know the rates
+    /// for any of the models.
+    fn input_cost_usd(&self, pricing: &BTreeMap<String, ModelPricing>) -> Option<InputCost> {
+        let mut total = None;
+        for (model, usage) in &self.model_usage {
+            let Some(rates) = ModelPricing::lookup(pricing, model) else {
+                continue;
+            };
+            *total.get_or_insert_with(InputCost::default) +=
+                rates.input_cost_usd(&TokenUsage::from(usage));
+        }
+        total
+    }
+}
+
+impl From<&ClaudeUsage> for TokenUsage {
+    fn from(usage: &ClaudeUsage) -> Self {
+        Self {
+            input: usage.input_tokens,
+            output: usage.output_tokens,
+            cache_read: usage.cache_read_input_tokens,
+            cache_write: usage.cache_creation_input_tokens,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct ClaudeModelUsage {
     input_tokens: u64,
@@ -278,6 +956,17 @@ struct ClaudeModelUsage {
     max_output_tokens: u64,
 }
 
+impl From<&ClaudeModelUsage> for TokenUsage {
+    fn from(usage: &ClaudeModelUsage) -> Self {
+        Self {
+            input: usage.input_tokens,
+            output: usage.output_tokens,
+            cache_read: usage.cache_read_input_tokens,
+            cache_write: usage.cache_creation_input_tokens,
+        }
+    }
+}
+
 impl Display for ClaudeModelUsage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -292,7 +981,67 @@ impl Display for ClaudeModelUsage {
     }
 }
 
-struct Dollars(f64);
+/// The prefixes to replace with `$TMPDIR`, longest first.
+fn temp_dir_prefixes(raw: &Path, canonicalize: bool, extra: &[String]) -> Vec<String> {
+    let mut temp_dirs = Vec::new();
+
+    // Add canonicalized path (e.g. /private/tmp on macOS).
+    if canonicalize {
+        match raw.canonicalize() {
+            Ok(canonical) => temp_dirs.push(canonical.to_string_lossy().into_owned()),
+            Err(err) => tracing::debug!("Failed to canonicalize {}: {err}", raw.display()),
+        }
+    }
+
+    // Add raw path if it differs from canonical.
+    for dir in std::iter::once(raw.to_string_lossy().into_owned()).chain(extra.iter().cloned()) {
+        if !dir.is_empty() && !temp_dirs.contains(&dir) {
+            temp_dirs.push(dir);
+        }
+    }
+
+    // Longest first so we don't partially replace a longer prefix.
+    temp_dirs.sort_by_key(|b| std::cmp::Reverse(b.len()));
+    temp_dirs
+}
+
+/// Render `text` as Markdown, or return it unchanged if `termimad` can't render it.
+///
+/// A message with an unclosed code fence (e.g. one cut off mid-stream) would render everything
+/// after the fence as code, so we don't try.
+fn render_markdown(text: &str, wrap_width: Option<usize>) -> Cow<'_, str> {
+    let fences = text
+        .lines()
+        .filter(|line| {
+            let line = line.trim_start();
+            line.starts_with("```") || line.starts_with("~~~")
+        })
+        .count();
+    if fences % 2 != 0 {
+        tracing::debug!("Not rendering Markdown with an unclosed code fence");
+        return Cow::Borrowed(text);
+    }
+
+    render_or_raw(text, || match wrap_width {
+        Some(width) => termimad::get_default_skin()
+            .text(text, Some(width))
+            .to_string(),
+        None => termimad::term_text(text).to_string(),
+    })
+}
+
+/// Run `render`, falling back to `text` if it panics.
+fn render_or_raw(text: &str, render: impl FnOnce() -> String) -> Cow<'_, str> {
+    match logging::catch_unwind_silently(render) {
+        Ok(rendered) => Cow::Owned(rendered),
+        Err(_) => {
+            tracing::debug!("termimad panicked; printing Markdown unrendered");
+            Cow::Borrowed(text)
+        }
+    }
+}
+
+pub struct Dollars(pub f64);
 
 impl Display for Dollars {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -304,7 +1053,7 @@ impl Display for Dollars {
     }
 }
 
-struct Tokens(u64);
+pub struct Tokens(pub u64);
 
 impl Display for Tokens {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -321,7 +1070,7 @@ impl Display for Tokens {
     }
 }
 
-struct HumanTime(Duration);
+pub struct HumanTime(pub Duration);
 
 impl Display for HumanTime {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -339,33 +1088,644 @@ impl Display for HumanTime {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use expect_test::expect;
 
-    #[test]
-    fn is_result_event_true() {
-        let writer = ClaudeEventWriter {
+    fn writer(options: DisplayOptions) -> ClaudeEventWriter {
+        ClaudeEventWriter {
             temp_dirs: vec![],
+            pricing: BTreeMap::new(),
             has_output: AtomicBool::new(false),
-        };
+            skipped: AtomicUsize::new(0),
+            options,
+        }
+    }
+
+    /// Render a recorded session from `tests/fixtures`, checking that every line parses.
+    fn replay(name: &str) -> String {
+        owo_colors::set_override(false);
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let writer = writer(DisplayOptions {
+            plain: true,
+            diff_lines: 10,
+            ..Default::default()
+        });
+        let mut output = String::new();
+        for line in contents.lines() {
+            match writer.display(line) {
+                Some(event) => output.push_str(&event.to_string()),
+                None => panic!("{name}: failed to parse {line}"),
+            }
+        }
+        assert_eq!(writer.skipped_events(), 0);
+        output
+    }
+
+    #[test]
+    fn replay_every_fixture() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let mut replayed = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let name = entry.unwrap().file_name();
+            let name = name.to_str().unwrap();
+            if name.ends_with(".jsonl") {
+                replay(name);
+                replayed += 1;
+            }
+        }
+        assert!(replayed > 0);
+    }
+
+    #[test]
+    fn replay_merge_success() {
+        expect![[r#"
+            I'll start by reading both sides of the conflict.> Read src/math.rs
+            > Grep "fn add" src
+            > Edit src/math.rs
+              - <<<<<<< ours
+              -     a + b
+              - =======
+              -     a.wrapping_add(b)
+              - >>>>>>> theirs
+              +     a.wrapping_add(b)
+            Both sides changed the body of `add`. Theirs avoids overflow panics, which is a **behavior fix**, so I kept it.Finished in 18.42s (17.31s API time). Total cost: $0.0874 (Salary: $34.2k/yr)
+            Usage by model:
+                claude-opus-4-6: 21 input, 344 output, 60.9k cache read, 5.1k cache write ($0.0874)
+            Session: 5d2f6a10-8c1e-4b7a-9f3d-2e6c1a0b9d84
+            Input cost: $0.0001 uncached, $0.0316 cache writes, $0.0305 cache reads (caching saved $0.2679)
+        "#]].assert_eq(&replay("merge-success.jsonl"));
+    }
+
+    #[test]
+    fn replay_failed_tool_call() {
+        expect![[r#"
+            > Write config.toml
+              │ <tool_use_error>File has not been read yet. Read it first before writing to it.</tool_use_error>
+            > Bash git log --oneline -3 -- config.toml …
+            The two sides disagree about the default port, and nothing in the history says which is right.
+
+            UNRESOLVED: ours sets `port = 8080` and theirs sets `port = 9090`.Finished in 9.15s (8.70s API time). Total cost: $0.0213 (Salary: $16.8k/yr)
+            Usage by model:
+                claude-sonnet-4-5: 16 input, 209 output, 36.4k cache read, 3.7k cache write ($0.0213)
+            Session: 9a7c3e21-4f0b-4d6e-8b12-5c9e0f7a3b41
+            Input cost: $0.0000 uncached, $0.0138 cache writes, $0.0109 cache reads (caching saved $0.0955)
+        "#]].assert_eq(&replay("failed-tool-call.jsonl"));
+    }
+
+    #[test]
+    fn replay_max_turns() {
+        expect![[r#"
+            > MultiEdit Cargo.toml
+              - serde = "1.0.200"
+              + serde = "1.0.210"
+              - toml = "0.8"
+              + toml = "0.9"
+            Failed after 4.21s and 2 turns: reached the maximum number of turns
+            Total cost: $0.0041
+            Session: c41e7b09-2d5a-4f83-b6e0-91a3d8c5f072
+        "#]]
+        .assert_eq(&replay("max-turns.jsonl"));
+    }
+
+    #[test]
+    fn skipped_events() {
+        let writer = writer(DisplayOptions::default());
+        assert!(
+            writer
+                .display(r#"{"type":"system","subtype":"init"}"#)
+                .is_some()
+        );
+        assert!(writer.display(r#"{"type":"stream_event"}"#).is_none());
+        assert!(writer.display("not json").is_none());
+        assert_eq!(writer.skipped_events(), 2);
+    }
+
+    #[test]
+    fn transient_errors() {
+        let writer = writer(DisplayOptions::default());
+        let transient = |line: &str| writer.display(line).unwrap().is_transient_error();
+        assert!(transient(
+            r#"{"type":"result","subtype":"success","is_error":true,"duration_ms":1200,"duration_api_ms":0,"num_turns":1,"result":"API Error: 529 {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}","total_cost_usd":0,"usage":{"input_tokens":0,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":0},"modelUsage":{}}"#
+        ));
+        assert!(transient(
+            r#"{"type":"result","subtype":"error_during_execution","is_error":true,"duration_ms":5000,"num_turns":3}"#
+        ));
+        assert!(!transient(
+            r#"{"type":"result","subtype":"error_max_turns","is_error":true,"duration_ms":61234,"num_turns":21}"#
+        ));
+        assert!(!transient(
+            r#"{"type":"result","subtype":"success","is_error":false,"duration_ms":100,"duration_api_ms":90,"num_turns":1,"result":"The API timed out twice in the tests, so I kept the longer timeout.","total_cost_usd":0.1,"usage":{"input_tokens":1,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":1},"modelUsage":{}}"#
+        ));
+    }
+
+    #[test]
+    fn is_result_event_true() {
+        let writer = writer(DisplayOptions::default());
         let line = r#"{"type":"result","subtype":"success","is_error":false,"duration_ms":30093,"duration_api_ms":30038,"num_turns":7,"result":"done","total_cost_usd":0.113,"usage":{"input_tokens":7,"cache_creation_input_tokens":3972,"cache_read_input_tokens":104455,"output_tokens":1451},"modelUsage":{}}"#;
         assert!(writer.display(line).unwrap().is_result());
     }
 
     #[test]
     fn is_result_event_false_assistant() {
-        let writer = ClaudeEventWriter {
-            temp_dirs: vec![],
-            has_output: AtomicBool::new(false),
-        };
+        let writer = writer(DisplayOptions::default());
         let line = r#"{"type":"assistant","message":{"model":"claude-opus-4-6","id":"msg_01","type":"message","role":"assistant","content":[{"type":"text","text":"hello"}]}}"#;
         assert!(!writer.display(line).unwrap().is_result());
     }
 
     #[test]
     fn is_result_event_false_invalid_json() {
+        let writer = writer(DisplayOptions::default());
+        assert!(writer.display("not json at all").is_none());
+    }
+
+    #[test]
+    fn error_result_event() {
+        let writer = writer(DisplayOptions::default());
+        let line = r#"{"type":"result","subtype":"error_max_turns","is_error":true,"duration_ms":61234,"duration_api_ms":60000,"num_turns":21,"total_cost_usd":0.42,"usage":{"input_tokens":7,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":1}}"#;
+        let event = writer.display(line).unwrap();
+        assert!(event.is_result());
+        assert_eq!(event.result_text(), None);
+        assert_eq!(event.total_cost_usd(), Some(0.42));
+        assert_eq!(
+            event.error_reason().as_deref(),
+            Some("reached the maximum number of turns")
+        );
+        assert!(event.to_string().contains(
+            "Failed after 1m 1s 234ms and 21 turns: reached the maximum number of turns"
+        ));
+    }
+
+    #[test]
+    fn error_result_during_execution() {
+        owo_colors::set_override(false);
+        let writer = writer(DisplayOptions::default());
+        let line = r#"{"type":"result","subtype":"error_during_execution","is_error":true,"duration_ms":2500,"duration_api_ms":2000,"num_turns":3,"result":"API Error: 529 Overloaded","total_cost_usd":0.02,"usage":{"input_tokens":7,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":1}}"#;
+        let event = writer.display(line).unwrap();
+        assert!(event.is_result());
+        assert_eq!(event.result_text(), None);
+        assert_eq!(
+            event.error_reason().as_deref(),
+            Some("error during execution")
+        );
+        assert_eq!(
+            event.to_string(),
+            "Failed after 2.50s and 3 turns: error during execution\n\
+             API Error: 529 Overloaded\n\
+             Total cost: $0.0200\n"
+        );
+    }
+
+    #[test]
+    fn error_result_unknown_subtype() {
+        let writer = writer(DisplayOptions::default());
+        let line = r#"{"type":"result","subtype":"error_max_budget_usd","is_error":true,"duration_ms":1000,"result":"Budget exceeded"}"#;
+        let event = writer.display(line).unwrap();
+        assert_eq!(
+            event.error_reason().as_deref(),
+            Some("error_max_budget_usd")
+        );
+    }
+
+    #[test]
+    fn plain_output_has_no_escape_codes() {
+        // Simulate stderr not being a terminal.
+        owo_colors::set_override(false);
+
+        let writer = writer(DisplayOptions {
+            plain: true,
+            ..Default::default()
+        });
+        let lines = [
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Both sides **changed** `add`:\n\n- left: types\n- right: order"}]}}"#,
+            r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Read","input":{"file_path":"/tmp/left.rs"}}]}}"#,
+            r#"{"type":"result","subtype":"success","is_error":false,"duration_ms":30093,"duration_api_ms":30038,"num_turns":7,"result":"done","total_cost_usd":0.113,"usage":{"input_tokens":7,"cache_creation_input_tokens":3972,"cache_read_input_tokens":104455,"output_tokens":1451},"modelUsage":{"claude-opus-4-6":{"inputTokens":7,"outputTokens":1451,"cacheReadInputTokens":104455,"cacheCreationInputTokens":3972,"webSearchRequests":0,"costUSD":0.113,"contextWindow":200000,"maxOutputTokens":32000}}}"#,
+        ];
+        let output: String = lines
+            .iter()
+            .map(|line| writer.display(line).unwrap().to_string())
+            .collect();
+        assert!(!output.contains('\x1b'), "{output:?}");
+        assert!(
+            output.starts_with("Both sides **changed** `add`:"),
+            "{output:?}"
+        );
+    }
+
+    #[test]
+    fn thinking_hidden_by_default() {
+        owo_colors::set_override(false);
+        let line = r#"{"type":"assistant","message":{"content":[{"type":"thinking","thinking":"Both sides renamed `add`.","signature":"abc"}]}}"#;
+
+        let output = writer(DisplayOptions::default())
+            .display(line)
+            .unwrap()
+            .to_string();
+        assert_eq!(output, "");
+
+        let output = writer(DisplayOptions {
+            show_thinking: true,
+            ..Default::default()
+        })
+        .display(line)
+        .unwrap()
+        .to_string();
+        assert_eq!(output, "Both sides renamed `add`.\n");
+    }
+
+    #[test]
+    fn thinking_before_text() {
+        owo_colors::set_override(false);
+        let thinking = r#"{"type":"assistant","message":{"content":[{"type":"thinking","thinking":"\nThe left side renamed `add`.\n","signature":"abc"}]}}"#;
+        let text = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"\n\nKeeping the rename."}]}}"#;
+
+        // Hidden thinking doesn't count as output, so the text's leading newlines are stripped.
+        let hidden = writer(DisplayOptions {
+            plain: true,
+            ..Default::default()
+        });
+        assert_eq!(hidden.display(thinking).unwrap().to_string(), "");
+        assert_eq!(
+            hidden.display(text).unwrap().to_string(),
+            "Keeping the rename."
+        );
+
+        // Shown thinking does, so the text is separated from it.
+        let shown = writer(DisplayOptions {
+            plain: true,
+            show_thinking: true,
+            ..Default::default()
+        });
+        assert_eq!(
+            shown.display(thinking).unwrap().to_string(),
+            "The left side renamed `add`.\n"
+        );
+        assert_eq!(
+            shown.display(text).unwrap().to_string(),
+            "\n\nKeeping the rename."
+        );
+    }
+
+    #[test]
+    fn quiet() {
+        owo_colors::set_override(false);
+        let quiet = writer(DisplayOptions {
+            plain: true,
+            quiet: true,
+            ..Default::default()
+        });
+        let text =
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Merging."}]}}"#;
+        let tool = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","id":"1","name":"Read","input":{"file_path":"/repo/src/lib.rs"}}]}}"#;
+        assert_eq!(quiet.display(text).unwrap().to_string(), "");
+        assert_eq!(quiet.display(tool).unwrap().to_string(), "");
+
+        let error = r#"{"type":"result","subtype":"error_max_turns","is_error":true,"duration_ms":1000,"num_turns":21}"#;
+        assert_ne!(quiet.display(error).unwrap().to_string(), "");
+    }
+
+    #[test]
+    fn tool_results() {
+        owo_colors::set_override(false);
+        let read = r#"{"type":"user","message":{"role":"user","content":[{"tool_use_id":"1","type":"tool_result","content":"     1\tfn add() {}\n     2\t\n     3\tfn sub() {}\n     4\t\n     5\tfn mul() {}\n     6\t\n     7\tfn div() {}\n"}]}}"#;
+        let bash = r#"{"type":"user","message":{"role":"user","content":[{"tool_use_id":"2","type":"tool_result","content":[{"type":"text","text":"error: could not compile"}],"is_error":true}]}}"#;
+
+        // Without `show_tool_results`, only failures are shown.
+        let hidden = writer(DisplayOptions::default());
+        assert_eq!(hidden.display(read).unwrap().to_string(), "");
+        assert_eq!(
+            hidden.display(bash).unwrap().to_string(),
+            "  │ error: could not compile\n"
+        );
+
+        let shown = writer(DisplayOptions {
+            show_tool_results: true,
+            ..Default::default()
+        });
+        let preview = [
+            "  │      1\tfn add() {}",
+            "  │      2\t",
+            "  │      3\tfn sub() {}",
+            "  │      4\t",
+            "  │      5\tfn mul() {}",
+            "  │ … 2 more lines",
+        ]
+        .map(|line| format!("{line}\n"))
+        .concat();
+        assert_eq!(shown.display(read).unwrap().to_string(), preview);
+        assert_eq!(
+            shown.display(bash).unwrap().to_string(),
+            "  │ error: could not compile\n"
+        );
+
+        let long = format!(
+            r#"{{"type":"user","message":{{"content":[{{"type":"tool_result","content":"{}"}}]}}}}"#,
+            "x".repeat(200)
+        );
+        assert_eq!(
+            shown.display(&long).unwrap().to_string(),
+            format!("  │ {}…\n", "x".repeat(120))
+        );
+    }
+
+    #[test]
+    fn failed_tool_call() {
+        owo_colors::set_override(false);
+        // As logged by `claude` 2.0.
+        let line = r#"{"type":"user","message":{"role":"user","content":[{"type":"tool_result","content":"<tool_use_error>File has not been read yet. Read it first before writing to it.</tool_use_error>","is_error":true,"tool_use_id":"toolu_01XyZ"}]},"parent_tool_use_id":null,"session_id":"6f0c8a52-1b7e-4a8e-9d1c-0c7e2b6c9a10","uuid":"0d4b0d62-3c55-4a44-8f0e-2f0f6b1e7d3a"}"#;
+        let shown = writer(DisplayOptions::default());
+        let event = shown.display(line).unwrap();
+        assert!(!event.is_assistant());
+        assert!(!event.is_result());
+        assert_eq!(
+            event.to_string(),
+            "  │ <tool_use_error>File has not been read yet. Read it first before writing to \
+             it.</tool_use_error>\n"
+        );
+
+        let quiet = writer(DisplayOptions {
+            quiet: true,
+            ..Default::default()
+        });
+        assert_eq!(quiet.display(line).unwrap().to_string(), "");
+    }
+
+    #[test]
+    fn wrap_width() {
+        let line = r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Both sides renamed the same function, so I kept the left side's name."}]}}"#;
+        let output = writer(DisplayOptions {
+            wrap_width: Some(24),
+            ..Default::default()
+        })
+        .display(line)
+        .unwrap()
+        .to_string();
+        assert_eq!(
+            output.lines().map(str::trim_end).collect::<Vec<_>>(),
+            [
+                "Both sides renamed the",
+                "same function, so I kept",
+                "the left side's name.",
+            ]
+        );
+    }
+
+    #[test]
+    fn unclosed_code_fence_is_not_rendered() {
+        let text = "Here's the resolution:\n\n```rust\nfn main() {\n    **not bold**\n";
+        let line = serde_json::json!({
+            "type": "assistant",
+            "message": {"content": [{"type": "text", "text": text}]},
+        })
+        .to_string();
+        let output = writer(DisplayOptions::default())
+            .display(&line)
+            .unwrap()
+            .to_string();
+        assert_eq!(output, text);
+
+        assert_eq!(render_markdown(text, Some(20)), text);
+    }
+
+    #[test]
+    fn render_panic_falls_back_to_raw_text() {
+        let text = "**bold**";
+        assert_eq!(render_or_raw(text, || panic!("termimad bug")), text);
+        assert_eq!(render_or_raw(text, || "rendered".to_owned()), "rendered");
+    }
+
+    #[test]
+    fn tool_use_summaries() {
+        owo_colors::set_override(false);
         let writer = ClaudeEventWriter {
-            temp_dirs: vec![],
+            temp_dirs: vec!["/tmp/merge".to_owned()],
+            pricing: BTreeMap::new(),
             has_output: AtomicBool::new(false),
+            skipped: AtomicUsize::new(0),
+            options: DisplayOptions::default(),
         };
-        assert!(writer.display("not json at all").is_none());
+        let tool_use = |name: &str, input: serde_json::Value| {
+            let line = serde_json::json!({
+                "type": "assistant",
+                "message": {"content": [{"type": "tool_use", "name": name, "input": input}]},
+            });
+            writer.display(&line.to_string()).unwrap().to_string()
+        };
+
+        assert_eq!(
+            tool_use(
+                "Read",
+                serde_json::json!({"file_path": "/tmp/merge/base.rs"})
+            ),
+            "> Read $TMPDIR/base.rs\n"
+        );
+        assert_eq!(
+            tool_use("Bash", serde_json::json!({"command": "git status"})),
+            "> Bash git status\n"
+        );
+        assert_eq!(
+            tool_use(
+                "Bash",
+                serde_json::json!({"command": "cargo check\ncargo test"})
+            ),
+            "> Bash cargo check …\n"
+        );
+        assert_eq!(
+            tool_use(
+                "Grep",
+                serde_json::json!({"pattern": r"fn\s+add", "path": "/tmp/merge/src/"})
+            ),
+            "> Grep \"fn\\s+add\" $TMPDIR/src/\n"
+        );
+        assert_eq!(
+            tool_use("Glob", serde_json::json!({"pattern": "**/*.rs"})),
+            "> Glob **/*.rs\n"
+        );
+        assert_eq!(
+            tool_use("TodoWrite", serde_json::json!({"todos": []})),
+            "> TodoWrite\n"
+        );
+    }
+
+    #[test]
+    fn result_json() {
+        let line = r#"{"type":"result","subtype":"success","is_error":false,"duration_ms":30093,"duration_api_ms":30038,"num_turns":7,"result":"done","total_cost_usd":0.113,"usage":{"input_tokens":7,"cache_creation_input_tokens":3972,"cache_read_input_tokens":104455,"output_tokens":1451},"modelUsage":{"claude-opus-4-6":{"inputTokens":7,"outputTokens":1451,"cacheReadInputTokens":104455,"cacheCreationInputTokens":3972,"webSearchRequests":0,"costUSD":0.113,"contextWindow":200000,"maxOutputTokens":32000}},"session_id":"abc"}"#;
+        let writer = writer(DisplayOptions::default());
+        let json = writer.display(line).unwrap().result_json().unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "is_error": false,
+                "duration_ms": 30093,
+                "duration_api_ms": 30038,
+                "num_turns": 7,
+                "result": "done",
+                "total_cost_usd": 0.113,
+                "usage": {
+                    "input_tokens": 7,
+                    "cache_creation_input_tokens": 3972,
+                    "cache_read_input_tokens": 104455,
+                    "output_tokens": 1451,
+                },
+                "modelUsage": {
+                    "claude-opus-4-6": {
+                        "inputTokens": 7,
+                        "outputTokens": 1451,
+                        "cacheReadInputTokens": 104455,
+                        "cacheCreationInputTokens": 3972,
+                        "webSearchRequests": 0,
+                        "costUSD": 0.113,
+                        "contextWindow": 200000,
+                        "maxOutputTokens": 32000,
+                    },
+                },
+                "session_id": "abc",
+            })
+        );
+
+        let error = r#"{"type":"result","subtype":"error_max_turns","is_error":true,"duration_ms":1000,"num_turns":21}"#;
+        assert_eq!(writer.display(error).unwrap().result_json(), None);
+    }
+
+    #[test]
+    fn budget_line() {
+        owo_colors::set_override(false);
+        let line = r#"{"type":"result","subtype":"success","is_error":false,"duration_ms":30093,"duration_api_ms":30038,"num_turns":7,"result":"done","total_cost_usd":0.113,"usage":{"input_tokens":7,"cache_creation_input_tokens":3972,"cache_read_input_tokens":104455,"output_tokens":1451},"modelUsage":{}}"#;
+        let output = writer(DisplayOptions {
+            max_cost_usd: Some(0.1),
+            ..Default::default()
+        })
+        .display(line)
+        .unwrap()
+        .to_string();
+        assert!(
+            output.ends_with("\nBudget: $0.1130 of $0.1000\n"),
+            "{output}"
+        );
+
+        let output = writer(DisplayOptions::default())
+            .display(line)
+            .unwrap()
+            .to_string();
+        assert!(!output.contains("Budget"), "{output}");
+    }
+
+    #[test]
+    fn input_cost_line() {
+        owo_colors::set_override(false);
+        let line = r#"{"type":"result","subtype":"success","is_error":false,"duration_ms":30093,"duration_api_ms":30038,"num_turns":7,"result":"done","total_cost_usd":0.113,"usage":{"input_tokens":100000,"cache_creation_input_tokens":1000000,"cache_read_input_tokens":2000000,"output_tokens":1451},"modelUsage":{"claude-sonnet-4-5":{"inputTokens":100000,"outputTokens":1451,"cacheReadInputTokens":2000000,"cacheCreationInputTokens":1000000,"webSearchRequests":0,"costUSD":4.67,"contextWindow":200000,"maxOutputTokens":64000},"gpt-5":{"inputTokens":100,"outputTokens":10,"cacheReadInputTokens":0,"cacheCreationInputTokens":0,"webSearchRequests":0,"costUSD":0.01,"contextWindow":200000,"maxOutputTokens":64000}}}"#;
+        let output = writer(DisplayOptions::default())
+            .display(line)
+            .unwrap()
+            .to_string();
+        assert!(
+            output.ends_with(
+                "\nInput cost: $0.3000 uncached, $3.7500 cache writes, $0.6000 cache reads \
+                 (caching saved $4.6500)\n"
+            ),
+            "{output}"
+        );
+
+        // Configured rates take precedence, and models we don't know the rates for are skipped.
+        let mut writer = writer(DisplayOptions::default());
+        writer.pricing = toml::from_str(
+            r#"
+            [claude-sonnet-4-5]
+            input = 1.0
+            output = 1.0
+            cache_read = 1.0
+            cache_write = 2.0
+            "#,
+        )
+        .unwrap();
+        let output = writer.display(line).unwrap().to_string();
+        assert!(
+            output.ends_with(
+                "\nInput cost: $0.1000 uncached, $2.0000 cache writes, $2.0000 cache reads \
+                 (caching cost $1.0000 extra)\n"
+            ),
+            "{output}"
+        );
+
+        let unknown = line.replace("claude-sonnet-4-5", "gpt-4");
+        let output = writer.display(&unknown).unwrap().to_string();
+        assert!(!output.contains("Input cost"), "{output}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn temp_dir_prefixes_canonicalize() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().canonicalize().unwrap().join("real");
+        std::fs::create_dir(&real).unwrap();
+        let link = real.parent().unwrap().join("link-to-real");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        let path = |path: &Path| path.to_str().unwrap().to_owned();
+
+        assert_eq!(
+            temp_dir_prefixes(&link, true, &[]),
+            [path(&link), path(&real)]
+        );
+        assert_eq!(temp_dir_prefixes(&link, false, &[]), [path(&link)]);
+        // A temp directory that can't be canonicalized is still replaced.
+        let missing = real.join("missing");
+        assert_eq!(temp_dir_prefixes(&missing, true, &[]), [path(&missing)]);
+    }
+
+    #[test]
+    fn temp_dir_prefixes_extra() {
+        owo_colors::set_override(false);
+        let prefixes = temp_dir_prefixes(
+            Path::new("/tmp"),
+            false,
+            &["/sandbox/tmp".to_owned(), "/tmp".to_owned(), String::new()],
+        );
+        assert_eq!(prefixes, ["/sandbox/tmp", "/tmp"]);
+
+        let mut writer = writer(DisplayOptions::default());
+        writer.temp_dirs = prefixes;
+        let line = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Read","input":{"file_path":"/sandbox/tmp/left.rs"}}]}}"#;
+        assert_eq!(
+            writer.display(line).unwrap().to_string(),
+            "> Read $TMPDIR/left.rs\n"
+        );
+    }
+
+    #[test]
+    fn edit_diffs() {
+        owo_colors::set_override(false);
+        let diffs = writer(DisplayOptions {
+            diff_lines: 2,
+            ..Default::default()
+        });
+
+        let edit = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Edit","input":{"file_path":"src/lib.rs","old_string":"let sum = add(1, 2);","new_string":"let total = add(1, 2, 3);\nprintln!(\"{total}\");"}}]}}"#;
+        expect![[r#"
+            > Edit src/lib.rs
+              - let sum = add(1, 2);
+              + let total = add(1, 2, 3);
+              + println!("{total}");
+        "#]]
+        .assert_eq(&diffs.display(edit).unwrap().to_string());
+
+        let multi_edit = r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"MultiEdit","input":{"file_path":"src/lib.rs","edits":[{"old_string":"a\nb\nc\nd","new_string":""},{"old_string":"x","new_string":"y"}]}}]}}"#;
+        expect![[r#"
+            > MultiEdit src/lib.rs
+              - a
+              - b
+              - … 2 more lines
+              - x
+              + y
+        "#]]
+        .assert_eq(&diffs.display(multi_edit).unwrap().to_string());
+
+        // Hidden by default.
+        let output = writer(DisplayOptions::default())
+            .display(edit)
+            .unwrap()
+            .to_string();
+        assert_eq!(output, "> Edit src/lib.rs\n");
     }
 }
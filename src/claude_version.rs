@@ -0,0 +1,159 @@
+use miette::miette;
+use std::fmt::Display;
+use std::process::Command;
+
+/// A `claude` CLI version, as printed by `claude --version` (e.g. `2.0.14 (Claude Code)`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClaudeVersion {
+    major: u64,
+    minor: u64,
+    patch: u64,
+}
+
+/// The oldest `claude` whose `stream-json` output we've checked our parsing against.
+const OLDEST_KNOWN_GOOD: ClaudeVersion = ClaudeVersion {
+    major: 1,
+    minor: 0,
+    patch: 0,
+};
+
+/// Newer major versions may change the event format.
+const NEWEST_KNOWN_GOOD_MAJOR: u64 = 2;
+
+impl ClaudeVersion {
+    /// Parse the output of `claude --version`.
+    pub fn parse(output: &str) -> Option<Self> {
+        let version = output.split_whitespace().next()?;
+        let version = version.strip_prefix('v').unwrap_or(version);
+        // Ignore pre-release and build suffixes like `-beta.1`.
+        let version = version.split(['-', '+']).next()?;
+        let mut parts = version.split('.').map(|part| part.parse().ok());
+        let version = Self {
+            major: parts.next()??,
+            minor: parts.next()??,
+            patch: parts.next()??,
+        };
+        parts.next().is_none().then_some(version)
+    }
+
+    pub fn is_known_good(&self) -> bool {
+        *self >= OLDEST_KNOWN_GOOD && self.major <= NEWEST_KNOWN_GOOD_MAJOR
+    }
+}
+
+impl Display for ClaudeVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The Claude Code install instructions, for when `claude` is missing.
+const INSTALL_URL: &str = "https://docs.anthropic.com/en/docs/claude-code/setup";
+
+/// Run `claude --version`, warning if it's a version we don't know how to parse events from.
+///
+/// Returns the version string for the summary log. Fails if `claude` can't be run at all, so
+/// that a missing install is reported up front rather than as an OS error partway through the
+/// merge. If `claude` runs but we can't get a version from it, that isn't fatal.
+pub fn check(claude_binary: &str) -> miette::Result<Option<String>> {
+    let output = match Command::new(claude_binary).arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).trim().to_owned()
+        }
+        Ok(output) => {
+            tracing::debug!(
+                "Failed to get the claude version: `{claude_binary} --version` exited with {}",
+                output.status
+            );
+            return Ok(None);
+        }
+        Err(err) => {
+            return Err(miette!(
+                help = format!(
+                    "Install Claude Code ({INSTALL_URL}), or set `claude_binary` in the config \
+                     file to its path"
+                ),
+                "Couldn't run `{claude_binary}`: {err}"
+            ));
+        }
+    };
+
+    match ClaudeVersion::parse(&output) {
+        Some(version) if version.is_known_good() => {
+            tracing::debug!("claude version {version}");
+        }
+        Some(version) => {
+            tracing::warn!(
+                "claude {version} is outside the tested range \
+                ({OLDEST_KNOWN_GOOD} to {NEWEST_KNOWN_GOOD_MAJOR}.x), so some events may not be \
+                displayed; check the event log for the full transcript"
+            );
+        }
+        None => {
+            tracing::warn!("Couldn't parse claude version {output:?}");
+        }
+    }
+
+    Ok(Some(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(major: u64, minor: u64, patch: u64) -> ClaudeVersion {
+        ClaudeVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    #[test]
+    fn parse_versions() {
+        assert_eq!(
+            ClaudeVersion::parse("2.0.14 (Claude Code)"),
+            Some(version(2, 0, 14))
+        );
+        assert_eq!(ClaudeVersion::parse("1.0.98\n"), Some(version(1, 0, 98)));
+        assert_eq!(ClaudeVersion::parse("v1.2.3"), Some(version(1, 2, 3)));
+        assert_eq!(
+            ClaudeVersion::parse("3.0.0-beta.1 (Claude Code)"),
+            Some(version(3, 0, 0))
+        );
+        assert_eq!(ClaudeVersion::parse("1.2"), None);
+        assert_eq!(ClaudeVersion::parse("1.2.3.4"), None);
+        assert_eq!(ClaudeVersion::parse("Claude Code"), None);
+        assert_eq!(ClaudeVersion::parse(""), None);
+    }
+
+    #[test]
+    fn known_good_range() {
+        assert!(!version(0, 2, 125).is_known_good());
+        assert!(version(1, 0, 0).is_known_good());
+        assert!(version(1, 0, 98).is_known_good());
+        assert!(version(2, 0, 14).is_known_good());
+        assert!(version(2, 99, 0).is_known_good());
+        assert!(!version(3, 0, 0).is_known_good());
+        // Compared numerically, not lexically.
+        assert!(version(1, 10, 0) > version(1, 9, 0));
+    }
+
+    #[test]
+    fn missing_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("claude");
+        let err = check(missing.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().starts_with("Couldn't run `"), "{err}");
+        assert!(
+            err.help().unwrap().to_string().contains(INSTALL_URL),
+            "{err:?}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_version_is_not_fatal() {
+        assert_eq!(check("false").unwrap(), None);
+    }
+}
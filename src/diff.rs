@@ -0,0 +1,97 @@
+use owo_colors::OwoColorize;
+use owo_colors::Stream::Stderr;
+use similar::ChangeTag;
+use similar::TextDiff;
+
+/// Lines of unchanged context around each change.
+const CONTEXT_LINES: usize = 3;
+
+/// A unified diff from `old` to `new`, colored if stderr supports it. Empty if they're the same.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let mut out = String::new();
+    for (i, group) in diff.grouped_ops(CONTEXT_LINES).iter().enumerate() {
+        if i == 0 {
+            let header = format!("--- {old_name}\n+++ {new_name}");
+            out.push_str(&format!(
+                "{}\n",
+                header.if_supports_color(Stderr, |text| text.bold())
+            ));
+        }
+        let (first, last) = (&group[0], &group[group.len() - 1]);
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        let header = format!(
+            "@@ -{},{} +{},{} @@",
+            old_range.start + 1,
+            old_range.len(),
+            new_range.start + 1,
+            new_range.len()
+        );
+        out.push_str(&format!(
+            "{}\n",
+            header.if_supports_color(Stderr, |text| text.cyan())
+        ));
+
+        for op in group {
+            for change in diff.iter_changes(op) {
+                let line = format!(
+                    "{}{}",
+                    match change.tag() {
+                        ChangeTag::Delete => '-',
+                        ChangeTag::Insert => '+',
+                        ChangeTag::Equal => ' ',
+                    },
+                    change.value().trim_end_matches('\n')
+                );
+                let line = match change.tag() {
+                    ChangeTag::Delete => line
+                        .if_supports_color(Stderr, |text| text.red())
+                        .to_string(),
+                    ChangeTag::Insert => line
+                        .if_supports_color(Stderr, |text| text.green())
+                        .to_string(),
+                    ChangeTag::Equal => line,
+                };
+                out.push_str(&line);
+                out.push('\n');
+                if change.missing_newline() {
+                    out.push_str("\\ No newline at end of file\n");
+                }
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::expect;
+
+    #[test]
+    fn unified() {
+        owo_colors::set_override(false);
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\n";
+        let new = "a\nb\nc\nd\nE\nf\ng\nh\ni\nj";
+        expect![[r#"
+            --- left.txt
+            +++ output.txt
+            @@ -2,8 +2,9 @@
+             b
+             c
+             d
+            -e
+            +E
+             f
+             g
+             h
+             i
+            +j
+            \ No newline at end of file
+        "#]]
+        .assert_eq(&unified_diff(old, new, "left.txt", "output.txt"));
+
+        assert_eq!(unified_diff(old, old, "left.txt", "output.txt"), "");
+    }
+}
@@ -0,0 +1,356 @@
+use crate::MergeArgs;
+use crate::Outcome;
+use crate::claude_json::Dollars;
+use crate::config;
+use crate::git;
+use crate::markers;
+use crate::progress;
+use crate::webhook;
+use clap::FromArgMatches;
+use miette::Context;
+use miette::IntoDiagnostic;
+use miette::miette;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(clap::Args, Debug)]
+pub struct EvalArgs {
+    /// A directory with a subdirectory for each conflict, containing `base`, `left`, `right`,
+    /// and `expected` files. The files may have an extension, like `base.rs`.
+    dir: PathBuf,
+
+    /// Path to a TOML config file, as for `merge`.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Extra options for each `merge`, after `--`.
+    #[arg(last = true, value_name = "MERGE_ARGS")]
+    merge_args: Vec<OsString>,
+}
+
+impl EvalArgs {
+    pub fn run(self, color: bool) -> miette::Result<()> {
+        let cases = find_cases(&self.dir)?;
+        if cases.is_empty() {
+            return Err(miette!(
+                "No conflicts found in {}; expected directories containing `base`, `left`, \
+                 `right`, and `expected` files",
+                self.dir.display()
+            ));
+        }
+
+        let mut report = EvalReport::default();
+        for case in &cases {
+            let result = self.eval_case(case, color);
+            match &result {
+                Ok(result) => {
+                    if let Some(cost) = result.cost_usd {
+                        report.total_cost_usd += cost;
+                    }
+                    println!("{}: {}", case.name, result.verdict);
+                }
+                Err(err) => println!("{}: failed: {err}", case.name),
+            }
+            report.add(result.ok().map(|result| result.verdict));
+        }
+        println!("{report}");
+        Ok(())
+    }
+
+    /// Merge one case in a temporary directory, so the fixtures aren't touched.
+    fn eval_case(&self, case: &Case, color: bool) -> miette::Result<CaseResult> {
+        let tmp = tempfile::tempdir().into_diagnostic()?;
+        let output = tmp.path().join(&case.filepath);
+        let conflicted = match git::merge_file(&case.base, &case.left, &case.right)? {
+            git::MergeFile::Clean(merged) | git::MergeFile::Conflicted(merged) => merged,
+        };
+        std::fs::write(&output, conflicted)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write {}", output.display()))?;
+
+        let mut args = vec![OsString::from("merge")];
+        if let Some(config) = &self.config {
+            args.extend(["--config".into(), config.into()]);
+        }
+        args.extend([
+            case.base.clone().into(),
+            case.left.clone().into(),
+            case.right.clone().into(),
+            "-o".into(),
+            output.clone().into(),
+            "-p".into(),
+            case.filepath.clone().into(),
+        ]);
+        args.extend(self.merge_args.iter().cloned());
+        let merge = <MergeArgs as clap::Args>::augment_args(clap::Command::new("merge"))
+            .try_get_matches_from(args)
+            .and_then(|matches| MergeArgs::from_arg_matches(&matches))
+            .into_diagnostic()?;
+
+        let config = config::load_config(merge.config.as_deref())?;
+        let mut merge_report = webhook::MergeReport::default();
+        let outcome = merge.resolve(
+            &config,
+            color,
+            &mut progress::ProgressReporter::disabled(),
+            &mut merge_report,
+        )?;
+        let resolved = std::fs::read_to_string(&output)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to read {}", output.display()))?;
+        let expected = std::fs::read_to_string(&case.expected)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to read {}", case.expected.display()))?;
+
+        let verdict = if !matches!(outcome, Outcome::Resolved) {
+            Verdict::Unresolved
+        } else if resolved == expected {
+            Verdict::Matched
+        } else if markers::find_conflict_markers(
+            &resolved,
+            merge.marker_size.unwrap_or(markers::DEFAULT_MARKER_SIZE),
+        )
+        .is_empty()
+        {
+            Verdict::MarkerFree
+        } else {
+            Verdict::Unresolved
+        };
+        Ok(CaseResult {
+            verdict,
+            cost_usd: merge_report.total_cost_usd,
+        })
+    }
+}
+
+/// One conflict to resolve, from a subdirectory of the eval directory.
+#[derive(Debug)]
+struct Case {
+    name: String,
+    base: PathBuf,
+    left: PathBuf,
+    right: PathBuf,
+    expected: PathBuf,
+    /// The path we tell Claude the file has: the case's name, with the fixtures' extension.
+    filepath: String,
+}
+
+/// Find the cases in `dir`, sorted by name. Subdirectories without all four files are skipped.
+fn find_cases(dir: &Path) -> miette::Result<Vec<Case>> {
+    let entries = std::fs::read_dir(dir)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to read {}", dir.display()))?;
+    let mut cases = Vec::new();
+    for entry in entries {
+        let path = entry.into_diagnostic()?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let find = |stem: &str| -> miette::Result<Option<PathBuf>> {
+            for entry in std::fs::read_dir(&path).into_diagnostic()? {
+                let file = entry.into_diagnostic()?.path();
+                if file.is_file() && file.file_stem().is_some_and(|file_stem| file_stem == stem) {
+                    return Ok(Some(file));
+                }
+            }
+            Ok(None)
+        };
+        let (Some(base), Some(left), Some(right), Some(expected)) = (
+            find("base")?,
+            find("left")?,
+            find("right")?,
+            find("expected")?,
+        ) else {
+            tracing::warn!(
+                "Skipping {}, which doesn't have `base`, `left`, `right`, and `expected` files",
+                path.display()
+            );
+            continue;
+        };
+        let filepath = match base.extension() {
+            Some(extension) => format!("{name}.{}", extension.to_string_lossy()),
+            None => name.clone(),
+        };
+        cases.push(Case {
+            name,
+            base,
+            left,
+            right,
+            expected,
+            filepath,
+        });
+    }
+    cases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(cases)
+}
+
+struct CaseResult {
+    verdict: Verdict,
+    cost_usd: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Verdict {
+    /// The resolution is exactly the expected file.
+    Matched,
+    /// The resolution differs from the expected file, but has no conflict markers.
+    MarkerFree,
+    /// Claude didn't resolve the conflict, or left conflict markers.
+    Unresolved,
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Verdict::Matched => "matched expected",
+            Verdict::MarkerFree => "marker-free, but differs from expected",
+            Verdict::Unresolved => "unresolved",
+        })
+    }
+}
+
+/// Totals over the cases in an eval.
+#[derive(Debug, Default, PartialEq)]
+struct EvalReport {
+    cases: usize,
+    matched: usize,
+    marker_free: usize,
+    total_cost_usd: f64,
+}
+
+impl EvalReport {
+    /// Count a case, with `None` for a merge which failed.
+    fn add(&mut self, verdict: Option<Verdict>) {
+        self.cases += 1;
+        match verdict {
+            Some(Verdict::Matched) => {
+                self.matched += 1;
+                self.marker_free += 1;
+            }
+            Some(Verdict::MarkerFree) => self.marker_free += 1,
+            Some(Verdict::Unresolved) | None => {}
+        }
+    }
+}
+
+impl Display for EvalReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let percent = |count: usize| 100.0 * count as f64 / self.cases.max(1) as f64;
+        writeln!(
+            f,
+            "Matched expected: {} of {} ({:.0}%)",
+            self.matched,
+            self.cases,
+            percent(self.matched)
+        )?;
+        writeln!(
+            f,
+            "Marker-free: {} of {} ({:.0}%)",
+            self.marker_free,
+            self.cases,
+            percent(self.marker_free)
+        )?;
+        write!(f, "Total cost: {}", Dollars(self.total_cost_usd))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Write a case to `dir/name`.
+    fn write_case(dir: &Path, name: &str, right: &str, expected: &str) {
+        let case = dir.join(name);
+        std::fs::create_dir(&case).unwrap();
+        std::fs::write(case.join("base.txt"), "a\nb\n").unwrap();
+        std::fs::write(case.join("left.txt"), "a\nleft\n").unwrap();
+        std::fs::write(case.join("right.txt"), right).unwrap();
+        std::fs::write(case.join("expected.txt"), expected).unwrap();
+    }
+
+    #[test]
+    fn find_cases_skips_incomplete_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        write_case(dir.path(), "b", "a\nright\n", "a\nleft\nright\n");
+        write_case(dir.path(), "a", "a\nright\n", "a\nleft\nright\n");
+        std::fs::remove_file(dir.path().join("b/expected.txt")).unwrap();
+        std::fs::write(dir.path().join("README"), "not a case").unwrap();
+
+        let cases = find_cases(dir.path()).unwrap();
+        assert_eq!(cases.len(), 1);
+        assert_eq!(cases[0].name, "a");
+        assert_eq!(cases[0].filepath, "a.txt");
+        assert_eq!(cases[0].expected, dir.path().join("a/expected.txt"));
+    }
+
+    #[test]
+    fn eval_with_fake_claude() {
+        let dir = tempfile::tempdir().unwrap();
+        let cases = dir.path().join("cases");
+        std::fs::create_dir(&cases).unwrap();
+        // The fake `claude` always resolves to "a\nleft\nright\n", which is what `both` expects,
+        // and is marker-free but not what `theirs` expects.
+        write_case(&cases, "both", "a\nright\n", "a\nleft\nright\n");
+        write_case(&cases, "theirs", "a\nright\n", "a\nright\n");
+
+        let claude = dir.path().join("claude");
+        std::fs::write(
+            &claude,
+            r#"#!/bin/sh
+if [ "$1" = --version ]; then
+    echo '2.1.0 (Claude Code)'
+    exit 0
+fi
+output=$(printf '%s\n' "$@" | sed -n 's/.*Write the resolved file to: //p' | head -n 1)
+printf 'a\nleft\nright\n' > "$output"
+echo '{"type":"result","subtype":"success","is_error":false,"duration_ms":100,"duration_api_ms":90,"num_turns":1,"result":"Done.","total_cost_usd":0.25,"usage":{"input_tokens":10,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":5},"modelUsage":{}}'
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(&claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let config = dir.path().join("config.toml");
+        std::fs::write(
+            &config,
+            format!("claude_binary = {claude:?}\nlog = false\n"),
+        )
+        .unwrap();
+
+        let args = EvalArgs {
+            dir: cases.clone(),
+            config: Some(config),
+            merge_args: Vec::new(),
+        };
+        let mut report = EvalReport::default();
+        for case in find_cases(&cases).unwrap() {
+            let result = args.eval_case(&case, false).unwrap();
+            report.add(Some(result.verdict));
+            report.total_cost_usd += result.cost_usd.unwrap();
+        }
+        assert_eq!(
+            report,
+            EvalReport {
+                cases: 2,
+                matched: 1,
+                marker_free: 2,
+                total_cost_usd: 0.5,
+            }
+        );
+        assert_eq!(
+            report.to_string(),
+            "Matched expected: 1 of 2 (50%)\nMarker-free: 2 of 2 (100%)\nTotal cost: $0.5000"
+        );
+        // The fixtures are left alone.
+        assert_eq!(
+            std::fs::read_to_string(cases.join("both/left.txt")).unwrap(),
+            "a\nleft\n"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use command_error::CommandExt;
+use miette::Context;
+use miette::IntoDiagnostic;
+use std::path::Path;
+use std::process::Command;
+
+/// Refs pointing at the commits on either side of an in-progress merge, rebase, or cherry-pick.
+const CONFLICTING_REFS: &[&str] = &["HEAD", "MERGE_HEAD", "REBASE_HEAD", "CHERRY_PICK_HEAD"];
+
+fn git(repo: &Path) -> Command {
+    let mut command = Command::new("git");
+    command.current_dir(repo);
+    command
+}
+
+/// Read the note attached to `rev`, if any.
+///
+/// Missing notes and refs that don't exist are not errors; they just mean there's nothing to show.
+pub fn notes_show(repo: &Path, rev: &str) -> Option<String> {
+    match git(repo).args(["notes", "show", rev]).output_checked_utf8() {
+        Ok(output) => Some(output.stdout.trim().to_owned()).filter(|note| !note.is_empty()),
+        Err(err) => {
+            tracing::debug!("No git note for {rev}: {err}");
+            None
+        }
+    }
+}
+
+/// Collect notes attached to the commits involved in the current conflict.
+pub fn conflict_notes(repo: &Path) -> Vec<(&'static str, String)> {
+    CONFLICTING_REFS
+        .iter()
+        .filter_map(|rev| notes_show(repo, rev).map(|note| (*rev, note)))
+        .collect()
+}
+
+fn rev_exists(repo: &Path, rev: &str) -> bool {
+    git(repo)
+        .args(["rev-parse", "--verify", "--quiet"])
+        .arg(format!("{rev}^{{commit}}"))
+        .output_checked_utf8()
+        .is_ok()
+}
+
+/// The diff of `path` from `since` to `rev`.
+pub fn diff_since(repo: &Path, since: &str, rev: &str, path: &str) -> miette::Result<String> {
+    let output = git(repo)
+        .arg("diff")
+        .arg(format!("{since}..{rev}"))
+        .args(["--", path])
+        .output_checked_utf8()?;
+    Ok(output.stdout)
+}
+
+/// The diffs each side of the current conflict made to `path` since `since`.
+pub fn conflict_diffs_since(
+    repo: &Path,
+    since: &str,
+    path: &str,
+) -> miette::Result<Vec<(&'static str, String)>> {
+    CONFLICTING_REFS
+        .iter()
+        .filter(|rev| rev_exists(repo, rev))
+        .map(|rev| Ok((*rev, diff_since(repo, since, rev, path)?)))
+        .collect()
+}
+
+/// The result of Git's own three-way merge of a file.
+pub enum MergeFile {
+    /// The merged contents, with no conflicts.
+    Clean(Vec<u8>),
+    /// The merged contents, with conflict markers.
+    Conflicted(Vec<u8>),
+}
+
+/// Merge `base`, `left`, and `right` with `git merge-file`, without writing anything.
+///
+/// Files Git can't merge at all, like binary files, are errors.
+pub fn merge_file(base: &Path, left: &Path, right: &Path) -> miette::Result<MergeFile> {
+    let output = Command::new("git")
+        .args(["merge-file", "--stdout"])
+        .args([left, base, right])
+        .output()
+        .into_diagnostic()
+        .wrap_err("Failed to run `git merge-file`")?;
+    // The exit code is the number of conflicts, or negative (255) if the merge failed.
+    match output.status.code() {
+        Some(0) => Ok(MergeFile::Clean(output.stdout)),
+        Some(1..=127) => Ok(MergeFile::Conflicted(output.stdout)),
+        _ => Err(miette::miette!(
+            "`git merge-file` couldn't merge {}: {}",
+            left.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git_with_identity(repo: &Path, args: &[&str]) -> Command {
+        let mut command = git(repo);
+        command
+            .args(args)
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@test.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@test.com");
+        command
+    }
+
+    fn git_ok(repo: &Path, args: &[&str]) {
+        git_with_identity(repo, args).output_checked_utf8().unwrap();
+    }
+
+    #[test]
+    fn notes_show_reads_notes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo = tmp.path();
+        git_ok(repo, &["init", "-b", "main"]);
+        git_ok(repo, &["commit", "--allow-empty", "-m", "first"]);
+
+        assert_eq!(notes_show(repo, "HEAD"), None);
+
+        git_ok(
+            repo,
+            &[
+                "notes",
+                "add",
+                "-m",
+                "`user_id` was renamed to `account_id`",
+            ],
+        );
+        assert_eq!(
+            notes_show(repo, "HEAD").as_deref(),
+            Some("`user_id` was renamed to `account_id`")
+        );
+
+        // No merge in progress, so only `HEAD` has a note.
+        assert_eq!(
+            conflict_notes(repo),
+            vec![("HEAD", "`user_id` was renamed to `account_id`".to_owned())]
+        );
+    }
+
+    #[test]
+    fn conflict_diffs_since_commit() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo = tmp.path();
+        git_ok(repo, &["init", "-b", "main"]);
+        std::fs::write(repo.join("lib.rs"), "fn add() {}\n").unwrap();
+        git_ok(repo, &["add", "lib.rs"]);
+        git_ok(repo, &["commit", "-m", "base"]);
+        git_ok(repo, &["tag", "since"]);
+
+        git_ok(repo, &["checkout", "-b", "theirs"]);
+        std::fs::write(repo.join("lib.rs"), "fn add(a: u32) {}\n").unwrap();
+        git_ok(repo, &["commit", "-am", "theirs"]);
+
+        git_ok(repo, &["checkout", "main"]);
+        std::fs::write(repo.join("lib.rs"), "fn sum() {}\n").unwrap();
+        git_ok(repo, &["commit", "-am", "ours"]);
+
+        // No merge in progress, so only `HEAD` is diffed.
+        let diffs = conflict_diffs_since(repo, "since", "lib.rs").unwrap();
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].0, "HEAD");
+        assert!(
+            diffs[0].1.contains("-fn add() {}\n+fn sum() {}\n"),
+            "{diffs:?}"
+        );
+
+        // The merge conflicts, leaving `MERGE_HEAD` behind.
+        assert!(
+            git_with_identity(repo, &["merge", "theirs"])
+                .output_checked()
+                .is_err()
+        );
+        let diffs = conflict_diffs_since(repo, "since", "lib.rs").unwrap();
+        assert_eq!(
+            diffs.iter().map(|(rev, _)| *rev).collect::<Vec<_>>(),
+            ["HEAD", "MERGE_HEAD"]
+        );
+        assert!(
+            diffs[1].1.contains("-fn add() {}\n+fn add(a: u32) {}\n"),
+            "{diffs:?}"
+        );
+
+        assert!(diff_since(repo, "no-such-ref", "HEAD", "lib.rs").is_err());
+    }
+
+    #[test]
+    fn merge_file_clean_conflicted_and_binary() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, contents: &[u8]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            path
+        };
+        let base = write("base", b"a\nb\nc\n");
+        let left = write("left", b"A\nb\nc\n");
+        let right = write("right", b"a\nb\nC\n");
+        match merge_file(&base, &left, &right).unwrap() {
+            MergeFile::Clean(merged) => assert_eq!(merged, b"A\nb\nC\n"),
+            MergeFile::Conflicted(_) => panic!("expected a clean merge"),
+        }
+        // Nothing is written.
+        assert_eq!(std::fs::read(&left).unwrap(), b"A\nb\nc\n");
+
+        let right = write("right", b"Z\nb\nc\n");
+        assert!(matches!(
+            merge_file(&base, &left, &right).unwrap(),
+            MergeFile::Conflicted(_)
+        ));
+
+        let binary = write("binary", b"\0\x01\x02");
+        assert!(merge_file(&base, &binary, &right).is_err());
+    }
+}
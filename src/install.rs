@@ -2,15 +2,44 @@ use clap::ValueEnum;
 use command_error::CommandExt;
 use command_error::Utf8ProgramAndArgs;
 use miette::Context;
+use miette::IntoDiagnostic;
 use miette::miette;
 use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
 use std::process::Command;
 
+/// The line `install --merge-driver` adds to Git's global attributes file, which makes Git use
+/// the `claude` merge driver instead of its own merge for every file changed on both sides.
+const GIT_ATTRIBUTES_LINE: &str = "* merge=claude";
+
 #[derive(clap::Args, Debug)]
 pub struct InstallArgs {
-    /// Programs to configure `claude-mergetool` for. Defaults to `git` and `jj` (if available).
+    /// Programs to configure `claude-mergetool` for. Defaults to `git`, `jj`, and `hg` (if
+    /// available).
     #[arg()]
     programs: Vec<InstallProgram>,
+
+    /// Whether `git mergetool` should keep `.orig` backups of conflicted files (sets
+    /// `mergetool.keepBackup`). Left unchanged if not given.
+    #[arg(long, value_name = "BOOL")]
+    keep_backup: Option<bool>,
+
+    /// Set Git's global `merge.conflictStyle` to `zdiff3`, so conflict markers include the base
+    /// version. This affects every merge, not just ones resolved with `claude-mergetool`.
+    #[arg(long)]
+    set_conflict_style: bool,
+
+    /// Install `claude-mergetool` as a Git merge driver, so `git merge` resolves conflicts
+    /// automatically, instead of as a tool for `git mergetool`. This also adds `* merge=claude`
+    /// to Git's global attributes file.
+    #[arg(long)]
+    merge_driver: bool,
+
+    /// Print the commands (and file edits) which would configure each program to stderr instead
+    /// of running them.
+    #[arg(long)]
+    dry_run: bool,
 }
 
 impl InstallArgs {
@@ -18,17 +47,64 @@ impl InstallArgs {
         if self.programs.is_empty() {
             self.programs = InstallProgram::default_values();
             if self.programs.is_empty() {
-                return Err(miette!("Neither `git` nor `jj` is available"));
+                return Err(miette!("None of `git`, `jj`, or `hg` is available"));
             }
         }
 
         tracing::debug!(programs = ?self.programs, "Determined programs to configure");
 
         for program in self.programs {
+            if self.dry_run {
+                for change in program.describe_install(
+                    self.keep_backup,
+                    self.set_conflict_style,
+                    self.merge_driver,
+                )? {
+                    eprintln!("{change}");
+                }
+                continue;
+            }
+
             tracing::info!("Configuring `claude-mergetool` for {program}");
-            program.install().wrap_err_with(|| {
-                format!("Failed to configure `claude-mergetool` for `{program}`")
+            program
+                .install(self.keep_backup, self.set_conflict_style, self.merge_driver)
+                .wrap_err_with(|| {
+                    format!("Failed to configure `claude-mergetool` for `{program}`")
+                })?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(clap::Args, Debug)]
+pub struct UninstallArgs {
+    /// Programs to remove the `claude-mergetool` configuration from. Defaults to `git`, `jj`, and
+    /// `hg` (if available).
+    #[arg()]
+    programs: Vec<InstallProgram>,
+}
+
+impl UninstallArgs {
+    pub fn run(mut self) -> miette::Result<()> {
+        if self.programs.is_empty() {
+            self.programs = InstallProgram::default_values();
+            if self.programs.is_empty() {
+                return Err(miette!("None of `git`, `jj`, or `hg` is available"));
+            }
+        }
+
+        tracing::debug!(programs = ?self.programs, "Determined programs to clean up");
+
+        for program in self.programs {
+            let removed = program.uninstall().wrap_err_with(|| {
+                format!("Failed to remove the `claude-mergetool` configuration for `{program}`")
             })?;
+            if removed {
+                tracing::info!("Removed the `claude-mergetool` configuration for {program}");
+            } else {
+                tracing::info!("`claude-mergetool` isn't configured for {program}");
+            }
         }
 
         Ok(())
@@ -42,6 +118,9 @@ enum InstallProgram {
 
     /// Install `claude-mergetool` as a merge tool for jj.
     Jj,
+
+    /// Install `claude-mergetool` as a merge tool for Mercurial.
+    Hg,
 }
 
 impl Display for InstallProgram {
@@ -55,6 +134,7 @@ impl InstallProgram {
         match self {
             InstallProgram::Git => "git",
             InstallProgram::Jj => "jj",
+            InstallProgram::Hg => "hg",
         }
     }
 
@@ -73,7 +153,9 @@ impl InstallProgram {
             .collect()
     }
 
-    fn config_set_command(&self, name: &str, value: &str) -> Command {
+    /// A command which sets `name` to `value` in the user's config, or `None` for Mercurial,
+    /// which can only set config by editing the file.
+    fn config_set_command(&self, name: &str, value: &str) -> Option<Command> {
         let mut command = Command::new(self.program());
         command.arg("config");
         command.arg("set");
@@ -84,14 +166,22 @@ impl InstallProgram {
             InstallProgram::Jj => {
                 command.arg("--user");
             }
+            InstallProgram::Hg => return None,
         }
         command.arg(name);
         command.arg(value);
-        command
+        Some(command)
     }
 
     fn config_set(&self, name: &str, value: &str) -> miette::Result<()> {
-        let mut command = self.config_set_command(name, value);
+        let Some(mut command) = self.config_set_command(name, value) else {
+            let path = hgrc_path()?;
+            tracing::info!("Setting {name} in {}", path.display());
+            let (section, key) = name.split_once('.').expect("config names have a section");
+            return edit_file(&path, |contents| {
+                Some(set_ini_value(contents, section, key, value))
+            });
+        };
         tracing::info!("$ {}", Utf8ProgramAndArgs::from(&command));
 
         let output = command.output_checked_utf8()?;
@@ -100,28 +190,679 @@ impl InstallProgram {
         Ok(())
     }
 
-    pub fn install(&self) -> miette::Result<()> {
+    /// The config sections `install` writes to.
+    fn config_sections(&self) -> &'static [&'static str] {
         match self {
-            InstallProgram::Git => {
-                self.config_set(
-                    "mergetool.claude.cmd",
-                    r#"claude-mergetool merge "$BASE" "$LOCAL" "$REMOTE" -o "$MERGED""#,
-                )?;
+            InstallProgram::Git => &["mergetool.claude", "merge.claude"],
+            InstallProgram::Jj | InstallProgram::Hg => &["merge-tools.claude"],
+        }
+    }
 
-                self.config_set("mergetool.claude.trustExitCode", "true")?;
+    /// A command which succeeds if `install` has configured `section`.
+    fn is_installed_command(&self, section: &str) -> Command {
+        let mut command = Command::new(self.program());
+        command.arg("config");
+        match self {
+            InstallProgram::Git => {
+                command.args(["--global", "--get-regexp"]);
+                command.arg(format!("^{}\\.", section.replace('.', "\\.")));
+            }
+            InstallProgram::Jj => {
+                command.arg("get");
+                command.arg(format!("{section}.program"));
+            }
+            InstallProgram::Hg => {
+                command.arg(format!("{section}.executable"));
+            }
+        }
+        command
+    }
 
-                self.config_set("mergetool.claude.trustExitCode", "true")?;
+    /// A command which removes `section` from the user's config, or `None` for Mercurial.
+    fn config_unset_command(&self, section: &str) -> Option<Command> {
+        let mut command = Command::new(self.program());
+        command.arg("config");
+        match self {
+            InstallProgram::Git => {
+                command.args(["--global", "--remove-section"]);
             }
             InstallProgram::Jj => {
-                self.config_set("merge-tools.claude.program", "claude-mergetool")?;
+                command.args(["unset", "--user"]);
+            }
+            InstallProgram::Hg => return None,
+        }
+        command.arg(section);
+        Some(command)
+    }
+
+    /// Remove the configuration written by `install`, returning whether there was any.
+    pub fn uninstall(&self) -> miette::Result<bool> {
+        let mut removed = false;
+        for section in self.config_sections() {
+            let mut command = self.is_installed_command(section);
+            tracing::debug!("$ {}", Utf8ProgramAndArgs::from(&command));
+            if let Err(err) = command.output_checked_utf8() {
+                tracing::debug!("{section} not configured: {err}");
+                continue;
+            }
 
-                self.config_set(
+            match self.config_unset_command(section) {
+                Some(mut command) => {
+                    tracing::info!("$ {}", Utf8ProgramAndArgs::from(&command));
+                    command.output_checked_utf8()?;
+                }
+                None => {
+                    let path = hgrc_path()?;
+                    tracing::info!("Removing {section} from {}", path.display());
+                    let (section, prefix) = section
+                        .split_once('.')
+                        .expect("config names have a section");
+                    edit_file(&path, |contents| {
+                        remove_ini_keys(contents, section, &format!("{prefix}."))
+                    })?;
+                }
+            }
+            removed = true;
+        }
+
+        if *self == InstallProgram::Git {
+            let path = git_attributes_path()?;
+            if remove_line(&path, GIT_ATTRIBUTES_LINE)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to update {}", path.display()))?
+            {
+                tracing::info!("Removed `{GIT_ATTRIBUTES_LINE}` from {}", path.display());
+                removed = true;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// The config settings `install` writes, in order.
+    fn config_values(
+        &self,
+        keep_backup: Option<bool>,
+        set_conflict_style: bool,
+        merge_driver: bool,
+    ) -> Vec<(&'static str, String)> {
+        match self {
+            InstallProgram::Git => {
+                let mut values = if merge_driver {
+                    vec![
+                        ("merge.claude.name", "claude-mergetool".to_owned()),
+                        (
+                            "merge.claude.driver",
+                            "claude-mergetool merge --git-merge-driver %O %A %B -l %L -p %P"
+                                .to_owned(),
+                        ),
+                    ]
+                } else {
+                    vec![
+                        (
+                            "mergetool.claude.cmd",
+                            r#"claude-mergetool merge "$BASE" "$LOCAL" "$REMOTE" -o "$MERGED""#
+                                .to_owned(),
+                        ),
+                        ("mergetool.claude.trustExitCode", "true".to_owned()),
+                    ]
+                };
+                if let Some(keep_backup) = keep_backup {
+                    values.push(("mergetool.keepBackup", keep_backup.to_string()));
+                }
+                if set_conflict_style {
+                    values.push(("merge.conflictStyle", "zdiff3".to_owned()));
+                }
+                values
+            }
+            InstallProgram::Jj => vec![
+                ("merge-tools.claude.program", "claude-mergetool".to_owned()),
+                (
                     "merge-tools.claude.merge-args",
-                    r#"["merge", "$base", "$left", "$right", "-o", "$output", "-p", "$path"]"#,
-                )?;
+                    r#"["merge", "$base", "$left", "$right", "-o", "$output", "-p", "$path"]"#
+                        .to_owned(),
+                ),
+            ],
+            InstallProgram::Hg => vec![
+                (
+                    "merge-tools.claude.executable",
+                    "claude-mergetool".to_owned(),
+                ),
+                (
+                    "merge-tools.claude.args",
+                    "merge $base $local $other -o $output".to_owned(),
+                ),
+                // Let Mercurial merge what it can first, so Claude only sees real conflicts.
+                ("merge-tools.claude.premerge", "keep".to_owned()),
+            ],
+        }
+    }
+
+    /// Describe what `install` would do, one command or file edit per line.
+    pub fn describe_install(
+        &self,
+        keep_backup: Option<bool>,
+        set_conflict_style: bool,
+        merge_driver: bool,
+    ) -> miette::Result<Vec<String>> {
+        let mut changes = Vec::new();
+        for (name, value) in self.config_values(keep_backup, set_conflict_style, merge_driver) {
+            match self.config_set_command(name, &value) {
+                Some(command) => changes.push(Utf8ProgramAndArgs::from(&command).to_string()),
+                None => {
+                    let (section, key) = name.split_once('.').expect("config names have a section");
+                    changes.push(format!(
+                        "# In [{section}] of {}: {key} = {value}",
+                        hgrc_path()?.display()
+                    ));
+                }
+            }
+        }
+        if merge_driver && *self == InstallProgram::Git {
+            changes.push(format!(
+                "# Add to {}: {GIT_ATTRIBUTES_LINE}",
+                git_attributes_path()?.display()
+            ));
+        }
+        Ok(changes)
+    }
+
+    pub fn install(
+        &self,
+        keep_backup: Option<bool>,
+        set_conflict_style: bool,
+        merge_driver: bool,
+    ) -> miette::Result<()> {
+        if set_conflict_style {
+            match self {
+                InstallProgram::Git => tracing::info!(
+                    "Setting `merge.conflictStyle` to `zdiff3` so conflict markers show the base \
+                    version. This applies to every merge, including ones you resolve by hand; \
+                    run `git config --global --unset merge.conflictStyle` to undo it"
+                ),
+                InstallProgram::Jj => tracing::info!(
+                    "--set-conflict-style only applies to Git; jj always includes the base in \
+                    its conflict markers"
+                ),
+                InstallProgram::Hg => {
+                    tracing::info!("--set-conflict-style only applies to Git")
+                }
+            }
+        }
+
+        if merge_driver && *self != InstallProgram::Git {
+            tracing::info!("--merge-driver only applies to Git; configuring {self}'s merge tool");
+        }
+
+        for (name, value) in self.config_values(keep_backup, set_conflict_style, merge_driver) {
+            self.config_set(name, &value)?;
+        }
+
+        if merge_driver && *self == InstallProgram::Git {
+            let path = git_attributes_path()?;
+            if add_line(&path, GIT_ATTRIBUTES_LINE)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to update {}", path.display()))?
+            {
+                tracing::info!(
+                    "Added `{GIT_ATTRIBUTES_LINE}` to {}: Git will run `claude-mergetool` for \
+                     every file changed on both sides of a merge, in every repository, and Claude \
+                     for the ones `git merge-file` can't merge cleanly",
+                    path.display()
+                );
             }
         }
 
         Ok(())
     }
 }
+
+/// Git's global attributes file: `core.attributesFile`, or `$XDG_CONFIG_HOME/git/attributes`.
+fn git_attributes_path() -> miette::Result<PathBuf> {
+    let mut command = Command::new("git");
+    command.args([
+        "config",
+        "--global",
+        "--path",
+        "--get",
+        "core.attributesFile",
+    ]);
+    if let Ok(output) = command.output_checked_utf8() {
+        let path = output.stdout.trim();
+        if !path.is_empty() {
+            return Ok(PathBuf::from(path));
+        }
+    }
+
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))
+        .map(|dir| dir.join("git").join("attributes"))
+        .ok_or_else(|| miette!("Couldn't find Git's global attributes file"))
+}
+
+/// The user's Mercurial config file: `~/.hgrc`, unless only `$XDG_CONFIG_HOME/hg/hgrc` exists.
+fn hgrc_path() -> miette::Result<PathBuf> {
+    let home = dirs::home_dir().ok_or_else(|| miette!("Couldn't find the home directory"))?;
+    let hgrc = home.join(".hgrc");
+    if !hgrc.exists() {
+        let xdg = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map_or_else(|| home.join(".config"), PathBuf::from)
+            .join("hg")
+            .join("hgrc");
+        if xdg.exists() {
+            return Ok(xdg);
+        }
+    }
+    Ok(hgrc)
+}
+
+/// Rewrite the file at `path` (treating a missing file as empty) with `edit`, which returns
+/// `None` to leave it alone.
+fn edit_file(path: &Path, edit: impl FnOnce(&str) -> Option<String>) -> miette::Result<()> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => {
+            return Err(err)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to read {}", path.display()));
+        }
+    };
+    if let Some(edited) = edit(&contents) {
+        std::fs::write(path, edited)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// The range of lines in `[section]` of an INI file, after the header.
+fn ini_section(lines: &[&str], section: &str) -> Option<std::ops::Range<usize>> {
+    let header = format!("[{section}]");
+    let start = lines.iter().position(|line| line.trim() == header)? + 1;
+    let end = lines[start..]
+        .iter()
+        .position(|line| line.trim_start().starts_with('['))
+        .map_or(lines.len(), |i| start + i);
+    Some(start..end)
+}
+
+/// The key of an INI `key = value` line.
+fn ini_key(line: &str) -> Option<&str> {
+    let (key, _) = line.split_once('=')?;
+    let key = key.trim();
+    (!key.starts_with(['#', ';'])).then_some(key)
+}
+
+/// Set `key` in `[section]` of an INI file, adding the section if it's missing.
+fn set_ini_value(contents: &str, section: &str, key: &str, value: &str) -> String {
+    let mut lines = contents.lines().collect::<Vec<_>>();
+    let setting = format!("{key} = {value}");
+    match ini_section(&lines, section) {
+        Some(range) => {
+            match range.clone().find(|&i| ini_key(lines[i]) == Some(key)) {
+                Some(i) => lines[i] = &setting,
+                None => {
+                    // After the section's last setting, before any blank lines.
+                    let end = range
+                        .clone()
+                        .rev()
+                        .find(|&i| !lines[i].trim().is_empty())
+                        .map_or(range.start, |i| i + 1);
+                    lines.insert(end, &setting);
+                }
+            }
+            lines.iter().map(|line| format!("{line}\n")).collect()
+        }
+        None => {
+            let mut contents = contents.to_owned();
+            if !contents.is_empty() {
+                if !contents.ends_with('\n') {
+                    contents.push('\n');
+                }
+                contents.push('\n');
+            }
+            contents.push_str(&format!("[{section}]\n{setting}\n"));
+            contents
+        }
+    }
+}
+
+/// Remove the keys starting with `prefix` from `[section]` of an INI file, or return `None` if
+/// there aren't any.
+fn remove_ini_keys(contents: &str, section: &str, prefix: &str) -> Option<String> {
+    let lines = contents.lines().collect::<Vec<_>>();
+    let range = ini_section(&lines, section)?;
+    let is_ours = |i: usize| {
+        range.contains(&i) && ini_key(lines[i]).is_some_and(|key| key.starts_with(prefix))
+    };
+    if !range.clone().any(is_ours) {
+        return None;
+    }
+    Some(
+        lines
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| !is_ours(i))
+            .map(|(_, line)| format!("{line}\n"))
+            .collect(),
+    )
+}
+
+/// Append `line` to the file at `path` unless it's already there, returning whether it was added.
+fn add_line(path: &Path, line: &str) -> std::io::Result<bool> {
+    let mut contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    if contents.lines().any(|existing| existing.trim() == line) {
+        return Ok(false);
+    }
+
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(line);
+    contents.push('\n');
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, contents)?;
+    Ok(true)
+}
+
+/// Remove `line` from the file at `path`, returning whether it was there.
+fn remove_line(path: &Path, line: &str) -> std::io::Result<bool> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err),
+    };
+    if !contents.lines().any(|existing| existing.trim() == line) {
+        return Ok(false);
+    }
+
+    let kept = contents
+        .lines()
+        .filter(|existing| existing.trim() != line)
+        .map(|existing| format!("{existing}\n"))
+        .collect::<String>();
+    std::fs::write(path, kept)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::expect;
+
+    #[test]
+    fn git_config_values() {
+        let names = |keep_backup| {
+            InstallProgram::Git
+                .config_values(keep_backup, false, false)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(None),
+            ["mergetool.claude.cmd", "mergetool.claude.trustExitCode"]
+        );
+        assert_eq!(
+            InstallProgram::Git.config_values(Some(false), false, false)[2],
+            ("mergetool.keepBackup", "false".to_owned())
+        );
+        assert_eq!(
+            InstallProgram::Git.config_values(None, true, false)[2],
+            ("merge.conflictStyle", "zdiff3".to_owned())
+        );
+        assert_eq!(
+            InstallProgram::Jj.config_values(None, true, false).len(),
+            InstallProgram::Jj.config_values(None, false, false).len()
+        );
+    }
+
+    #[test]
+    fn git_config_set_commands() {
+        let program = InstallProgram::Git;
+        let commands = program
+            .config_values(None, false, false)
+            .into_iter()
+            .map(|(name, value)| {
+                Utf8ProgramAndArgs::from(&program.config_set_command(name, &value).unwrap())
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        expect![[r#"
+            [
+                "git config set --global mergetool.claude.cmd 'claude-mergetool merge \"$BASE\" \"$LOCAL\" \"$REMOTE\" -o \"$MERGED\"'",
+                "git config set --global mergetool.claude.trustExitCode true",
+            ]
+        "#]]
+        .assert_debug_eq(&commands);
+    }
+
+    #[test]
+    fn git_merge_driver_config_values() {
+        let program = InstallProgram::Git;
+        let commands = program
+            .config_values(None, false, true)
+            .into_iter()
+            .map(|(name, value)| {
+                Utf8ProgramAndArgs::from(&program.config_set_command(name, &value).unwrap())
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        expect![[r#"
+            [
+                "git config set --global merge.claude.name claude-mergetool",
+                "git config set --global merge.claude.driver 'claude-mergetool merge --git-merge-driver %O %A %B -l %L -p %P'",
+            ]
+        "#]]
+        .assert_debug_eq(&commands);
+        assert_eq!(
+            InstallProgram::Jj.config_values(None, false, true),
+            InstallProgram::Jj.config_values(None, false, false)
+        );
+    }
+
+    #[test]
+    fn git_attributes_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("git").join("attributes");
+        assert!(!remove_line(&path, GIT_ATTRIBUTES_LINE).unwrap());
+
+        assert!(add_line(&path, GIT_ATTRIBUTES_LINE).unwrap());
+        assert!(!add_line(&path, GIT_ATTRIBUTES_LINE).unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "* merge=claude\n");
+
+        std::fs::write(&path, "*.png binary").unwrap();
+        assert!(add_line(&path, GIT_ATTRIBUTES_LINE).unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "*.png binary\n* merge=claude\n"
+        );
+
+        assert!(remove_line(&path, GIT_ATTRIBUTES_LINE).unwrap());
+        assert!(!remove_line(&path, GIT_ATTRIBUTES_LINE).unwrap());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "*.png binary\n");
+    }
+
+    #[test]
+    fn uninstall_commands() {
+        let commands = |program: InstallProgram| {
+            program
+                .config_sections()
+                .iter()
+                .flat_map(|section| {
+                    [
+                        Some(program.is_installed_command(section)),
+                        program.config_unset_command(section),
+                    ]
+                })
+                .flatten()
+                .map(|command| Utf8ProgramAndArgs::from(&command).to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            commands(InstallProgram::Git),
+            [
+                r"git config --global --get-regexp '^mergetool\.claude\.'",
+                "git config --global --remove-section mergetool.claude",
+                r"git config --global --get-regexp '^merge\.claude\.'",
+                "git config --global --remove-section merge.claude",
+            ]
+        );
+        assert_eq!(
+            commands(InstallProgram::Jj),
+            [
+                "jj config get merge-tools.claude.program",
+                "jj config unset --user merge-tools.claude",
+            ]
+        );
+        // Mercurial's config file is edited directly.
+        assert_eq!(
+            commands(InstallProgram::Hg),
+            ["hg config merge-tools.claude.executable"]
+        );
+    }
+
+    #[test]
+    fn describe_install() {
+        expect![[r#"
+            [
+                "jj config set --user merge-tools.claude.program claude-mergetool",
+                "jj config set --user merge-tools.claude.merge-args '[\"merge\", \"$base\", \"$left\", \"$right\", \"-o\", \"$output\", \"-p\", \"$path\"]'",
+            ]
+        "#]]
+        .assert_debug_eq(
+            &InstallProgram::Jj
+                .describe_install(None, true, true)
+                .unwrap(),
+        );
+
+        let changes = InstallProgram::Hg
+            .describe_install(None, false, false)
+            .unwrap();
+        assert_eq!(changes.len(), 3);
+        assert!(
+            changes[0].starts_with("# In [merge-tools] of "),
+            "{changes:?}"
+        );
+        assert!(
+            changes[0].ends_with(": claude.executable = claude-mergetool"),
+            "{changes:?}"
+        );
+
+        let changes = InstallProgram::Git
+            .describe_install(Some(false), false, true)
+            .unwrap();
+        assert_eq!(
+            changes[..3],
+            [
+                "git config set --global merge.claude.name claude-mergetool",
+                "git config set --global merge.claude.driver 'claude-mergetool merge --git-merge-driver %O %A %B -l %L -p %P'",
+                "git config set --global mergetool.keepBackup false",
+            ]
+        );
+        assert!(
+            changes[3].starts_with("# Add to ") && changes[3].ends_with(": * merge=claude"),
+            "{changes:?}"
+        );
+    }
+
+    #[test]
+    fn hg_config_values() {
+        let program = InstallProgram::Hg;
+        assert!(program.config_set_command("ui.merge", "claude").is_none());
+
+        let contents = program
+            .config_values(Some(true), true, true)
+            .into_iter()
+            .fold(
+                "[ui]\nusername = Jane <jane@example.com>\n".to_owned(),
+                |contents, (name, value)| {
+                    let (section, key) = name.split_once('.').unwrap();
+                    set_ini_value(&contents, section, key, &value)
+                },
+            );
+        expect![[r#"
+            [ui]
+            username = Jane <jane@example.com>
+
+            [merge-tools]
+            claude.executable = claude-mergetool
+            claude.args = merge $base $local $other -o $output
+            claude.premerge = keep
+        "#]]
+        .assert_eq(&contents);
+
+        let removed = remove_ini_keys(&contents, "merge-tools", "claude.").unwrap();
+        assert_eq!(
+            removed,
+            "[ui]\nusername = Jane <jane@example.com>\n\n[merge-tools]\n"
+        );
+        assert_eq!(remove_ini_keys(&removed, "merge-tools", "claude."), None);
+    }
+
+    #[test]
+    fn ini_editing() {
+        let contents = "\
+[merge-tools]
+kdiff3.args = $base $local $other -o $output
+# claude.args = old
+claude.args = old
+
+[ui]
+merge = kdiff3
+";
+        expect![[r#"
+            [merge-tools]
+            kdiff3.args = $base $local $other -o $output
+            # claude.args = old
+            claude.args = new
+
+            [ui]
+            merge = kdiff3
+        "#]]
+        .assert_eq(&set_ini_value(
+            contents,
+            "merge-tools",
+            "claude.args",
+            "new",
+        ));
+        expect![[r#"
+            [merge-tools]
+            kdiff3.args = $base $local $other -o $output
+            # claude.args = old
+            claude.args = old
+            claude.premerge = keep
+
+            [ui]
+            merge = kdiff3
+        "#]]
+        .assert_eq(&set_ini_value(
+            contents,
+            "merge-tools",
+            "claude.premerge",
+            "keep",
+        ));
+        expect![[r#"
+            [merge-tools]
+            kdiff3.args = $base $local $other -o $output
+            # claude.args = old
+
+            [ui]
+            merge = kdiff3
+        "#]]
+        .assert_eq(&remove_ini_keys(contents, "merge-tools", "claude.").unwrap());
+        assert_eq!(remove_ini_keys(contents, "extensions", "claude."), None);
+    }
+}
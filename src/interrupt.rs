@@ -0,0 +1,25 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Catch Ctrl-C, so that instead of dying with `claude` we can put the output back and record the
+/// aborted merge in the summary log. `claude` gets the same Ctrl-C and exits on its own.
+///
+/// Pressing Ctrl-C again exits immediately, in case `claude` doesn't.
+pub fn install_handler() {
+    let result = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, SeqCst) {
+            // Shells report death by SIGINT as 128 + 2.
+            std::process::exit(130);
+        }
+    });
+    if let Err(err) = result {
+        tracing::debug!("Failed to install a Ctrl-C handler: {err}");
+    }
+}
+
+/// Whether the user has pressed Ctrl-C.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(SeqCst)
+}
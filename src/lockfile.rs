@@ -0,0 +1,186 @@
+use command_error::CommandExt;
+use command_error::Utf8ProgramAndArgs;
+use miette::Context;
+use miette::IntoDiagnostic;
+use std::ffi::OsStr;
+use std::fmt::Display;
+use std::path::Path;
+use std::process::Command;
+
+/// A lockfile with a deterministic resolution that doesn't need Claude.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lockfile {
+    Cargo,
+    Npm,
+    Nix,
+}
+
+impl Display for Lockfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Lockfile::Cargo => write!(f, "Cargo.lock"),
+            Lockfile::Npm => write!(f, "package-lock.json"),
+            Lockfile::Nix => write!(f, "flake.lock"),
+        }
+    }
+}
+
+/// How to resolve a conflicted lockfile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// Take our side, then run a command in the lockfile's directory which updates it in place
+    /// to account for their changes to the manifest.
+    Regenerate {
+        program: &'static str,
+        args: &'static [&'static str],
+    },
+}
+
+impl Lockfile {
+    /// Detect a lockfile by its file name.
+    pub fn detect(filepath: &Path) -> Option<Self> {
+        match filepath.file_name()?.to_str()? {
+            "Cargo.lock" => Some(Lockfile::Cargo),
+            "package-lock.json" => Some(Lockfile::Npm),
+            "flake.lock" => Some(Lockfile::Nix),
+            _ => None,
+        }
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        match self {
+            // `cargo metadata` adds missing entries to `Cargo.lock` without upgrading anything else.
+            Lockfile::Cargo => Strategy::Regenerate {
+                program: "cargo",
+                args: &["metadata", "--format-version=1"],
+            },
+            Lockfile::Npm => Strategy::Regenerate {
+                program: "npm",
+                args: &["install", "--package-lock-only", "--ignore-scripts"],
+            },
+            Lockfile::Nix => Strategy::Regenerate {
+                program: "nix",
+                args: &["flake", "lock"],
+            },
+        }
+    }
+
+    /// The command which regenerates the lockfile at `filepath`.
+    pub fn command(&self, filepath: &Path) -> Command {
+        match self.strategy() {
+            Strategy::Regenerate { program, args } => command_in_dir(program, args, filepath),
+        }
+    }
+
+    /// Resolve the lockfile at `filepath` (relative to the current directory), starting from
+    /// `left`, and write the result to `output`.
+    pub fn resolve(&self, left: &Path, filepath: &Path, output: &Path) -> miette::Result<()> {
+        tracing::info!("Resolving {filepath:?} as a {self} without Claude");
+
+        match self.strategy() {
+            Strategy::Regenerate { .. } => {
+                regenerate(self.command(filepath), left, filepath, output)
+            }
+        }
+    }
+}
+
+/// A command which runs `program` with `args` in the directory containing `filepath`.
+pub fn command_in_dir(
+    program: impl AsRef<OsStr>,
+    args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    filepath: &Path,
+) -> Command {
+    let mut command = Command::new(program);
+    command.args(args);
+    if let Some(dir) = filepath.parent().filter(|dir| *dir != "") {
+        command.current_dir(dir);
+    }
+    command
+}
+
+/// Restore our side of `filepath` from `left`, run `command` to update it in place, and write
+/// the result to `output`.
+pub fn regenerate(
+    mut command: Command,
+    left: &Path,
+    filepath: &Path,
+    output: &Path,
+) -> miette::Result<()> {
+    std::fs::copy(left, filepath)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to restore our side of {filepath:?}"))?;
+
+    tracing::info!("$ {}", Utf8ProgramAndArgs::from(&command));
+    command.output_checked_utf8()?;
+
+    // Git's `$MERGED` is usually `filepath` itself; copying a file onto itself truncates it.
+    if filepath.canonicalize().ok() != output.canonicalize().ok() {
+        std::fs::copy(filepath, output)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write resolved {filepath:?} to {output:?}"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_lockfiles() {
+        assert_eq!(
+            Lockfile::detect(Path::new("Cargo.lock")),
+            Some(Lockfile::Cargo)
+        );
+        assert_eq!(
+            Lockfile::detect(Path::new("web/package-lock.json")),
+            Some(Lockfile::Npm)
+        );
+        assert_eq!(
+            Lockfile::detect(Path::new("flake.lock")),
+            Some(Lockfile::Nix)
+        );
+        assert_eq!(Lockfile::detect(Path::new("Cargo.toml")), None);
+        assert_eq!(Lockfile::detect(Path::new("docs/flake.lock.md")), None);
+    }
+
+    #[test]
+    fn strategy_dispatch() {
+        assert_eq!(
+            Lockfile::Cargo.strategy(),
+            Strategy::Regenerate {
+                program: "cargo",
+                args: &["metadata", "--format-version=1"],
+            }
+        );
+        assert_eq!(
+            Lockfile::Npm.strategy(),
+            Strategy::Regenerate {
+                program: "npm",
+                args: &["install", "--package-lock-only", "--ignore-scripts"],
+            }
+        );
+        assert_eq!(
+            Lockfile::Nix.strategy(),
+            Strategy::Regenerate {
+                program: "nix",
+                args: &["flake", "lock"],
+            }
+        );
+    }
+
+    #[test]
+    fn command_runs_in_lockfile_dir() {
+        let command = Lockfile::Cargo.command(Path::new("crates/foo/Cargo.lock"));
+        assert_eq!(
+            Utf8ProgramAndArgs::from(&command).to_string(),
+            "cargo metadata '--format-version=1'"
+        );
+        assert_eq!(command.get_current_dir(), Some(Path::new("crates/foo")));
+
+        let command = Lockfile::Nix.command(Path::new("flake.lock"));
+        assert_eq!(command.get_current_dir(), None);
+    }
+}
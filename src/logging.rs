@@ -1,6 +1,47 @@
+use std::cell::Cell;
 use std::fs::{self, File, OpenOptions};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The most recent panic message, recorded by [`install_panic_hook`] so that [`MergeLogger`] can
+/// write it to the event log while unwinding.
+static LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+thread_local! {
+    /// Set by [`catch_unwind_silently`] while it runs a closure whose panics we expect to handle.
+    static SILENCE_PANICS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Record panic messages for [`MergeLogger`], then defer to the existing panic hook.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if SILENCE_PANICS.get() {
+            return;
+        }
+        if let Ok(mut last_panic) = LAST_PANIC.lock() {
+            *last_panic = Some(info.to_string());
+        }
+        previous(info);
+    }));
+}
+
+/// Like [`std::panic::catch_unwind`], but a panic in `f` isn't printed or recorded for the event
+/// log, because the caller recovers from it.
+pub fn catch_unwind_silently<T>(f: impl FnOnce() -> T) -> std::thread::Result<T> {
+    let silenced = SILENCE_PANICS.replace(true);
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+    SILENCE_PANICS.set(silenced);
+    result
+}
+
+const SUMMARY_FILE_NAME: &str = "summary.jsonl";
+
+/// The summary log, with a line for each merge.
+pub fn summary_path() -> Option<PathBuf> {
+    Some(log_dir()?.join(SUMMARY_FILE_NAME))
+}
 
 /// Resolve the platform-appropriate log directory, creating it if needed.
 fn log_dir() -> Option<PathBuf> {
@@ -18,8 +59,48 @@ fn log_dir() -> Option<PathBuf> {
     Some(dir)
 }
 
-fn format_timestamp() -> String {
-    jiff::Zoned::now().strftime("%Y-%m-%dT%H-%M-%S").to_string()
+/// The format of the timestamp at the start of each event log's file name.
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H-%M-%S";
+
+pub fn format_timestamp() -> String {
+    jiff::Zoned::now().strftime(TIMESTAMP_FORMAT).to_string()
+}
+
+/// Delete event logs in `dir` from before `cutoff`, leaving the summary log and any files we
+/// don't recognize. Returns how many logs were deleted.
+fn prune_event_logs(dir: &Path, cutoff: jiff::civil::DateTime) -> usize {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::warn!("Failed to read log directory {}: {e}", dir.display());
+            return 0;
+        }
+    };
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if name == SUMMARY_FILE_NAME || !name.ends_with(".jsonl") {
+            continue;
+        }
+        // `2026-01-31T12-00-00`, the same length as `TIMESTAMP_FORMAT`'s output.
+        let Some(timestamp) = name.get(.."2026-01-31T12-00-00".len()) else {
+            continue;
+        };
+        let Ok(timestamp) = jiff::civil::DateTime::strptime(TIMESTAMP_FORMAT, timestamp) else {
+            continue;
+        };
+        if timestamp < cutoff {
+            match fs::remove_file(entry.path()) {
+                Ok(()) => removed += 1,
+                Err(e) => tracing::warn!("Failed to remove old event log {name}: {e}"),
+            }
+        }
+    }
+    removed
 }
 
 fn sanitize_filepath(s: &str) -> String {
@@ -31,27 +112,82 @@ fn sanitize_filepath(s: &str) -> String {
         .collect()
 }
 
+/// Append a line to the file at `path`, holding a lock so that concurrent merges don't interleave
+/// their lines.
+fn append_line(path: &Path, line: &str) -> std::io::Result<()> {
+    let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+    let locked = match f.lock() {
+        Ok(()) => true,
+        // Some network filesystems don't support locks.
+        Err(err) if err.kind() == std::io::ErrorKind::Unsupported => {
+            tracing::debug!("Can't lock {}: {err}", path.display());
+            false
+        }
+        Err(err) => return Err(err),
+    };
+    // One write call per line, so the line is written whole even if the lock is unsupported.
+    f.write_all(format!("{line}\n").as_bytes())?;
+    if locked {
+        f.unlock()?;
+    }
+    Ok(())
+}
+
+/// Generate a unique id for a merge, used to correlate its event log and summary line.
+pub fn new_run_id() -> String {
+    ulid::Ulid::new().to_string()
+}
+
 pub struct MergeLogger {
+    run_id: String,
+    /// The `claude --version` output, recorded in the summary log.
+    claude_version: Option<String>,
+    /// The last session ID `claude` reported, recorded in the summary log.
+    session_id: Option<String>,
     event_file: Option<File>,
     summary_path: Option<PathBuf>,
 }
 
 impl MergeLogger {
-    pub fn new(filepath: Option<&str>) -> Self {
-        let dir = match log_dir() {
-            Some(d) => d,
-            None => {
-                return Self {
-                    event_file: None,
-                    summary_path: None,
-                };
-            }
+    /// Create a logger in the log directory, first deleting event logs older than
+    /// `retention_days`, if given.
+    pub fn new(filepath: Option<&str>, run_id: &str, retention_days: Option<u64>) -> Self {
+        let Some(dir) = log_dir() else {
+            return Self::disabled(run_id);
         };
 
-        let summary_path = Some(dir.join("summary.jsonl"));
+        if let Some(days) = retention_days {
+            let cutoff = jiff::Span::new()
+                .try_days(i64::try_from(days).unwrap_or(i64::MAX))
+                .and_then(|span| jiff::Zoned::now().datetime().checked_sub(span));
+            match cutoff {
+                Ok(cutoff) => {
+                    let removed = prune_event_logs(&dir, cutoff);
+                    tracing::debug!("Removed {removed} event logs older than {days} days");
+                }
+                Err(e) => tracing::warn!("Invalid log retention of {days} days: {e}"),
+            }
+        }
+
+        Self::in_dir(&dir, filepath, run_id)
+    }
+
+    /// A logger which doesn't write anything, for `--no-log`.
+    pub fn disabled(run_id: &str) -> Self {
+        Self {
+            run_id: run_id.to_owned(),
+            claude_version: None,
+            session_id: None,
+            event_file: None,
+            summary_path: None,
+        }
+    }
+
+    pub(crate) fn in_dir(dir: &Path, filepath: Option<&str>, run_id: &str) -> Self {
+        let summary_path = Some(dir.join(SUMMARY_FILE_NAME));
 
         let sanitized = filepath.map_or_else(|| "unknown".to_string(), sanitize_filepath);
-        let filename = format!("{}_{}.jsonl", format_timestamp(), sanitized);
+        let filename = format!("{}_{}_{}.jsonl", format_timestamp(), sanitized, run_id);
         let event_file = match File::create(dir.join(&filename)) {
             Ok(f) => Some(f),
             Err(e) => {
@@ -61,11 +197,22 @@ impl MergeLogger {
         };
 
         Self {
+            run_id: run_id.to_owned(),
+            claude_version: None,
+            session_id: None,
             event_file,
             summary_path,
         }
     }
 
+    pub fn set_claude_version(&mut self, version: Option<String>) {
+        self.claude_version = version;
+    }
+
+    pub fn set_session_id(&mut self, session_id: &str) {
+        self.session_id = Some(session_id.to_owned());
+    }
+
     pub fn log_event(&mut self, line: &str) {
         if let Some(f) = &mut self.event_file
             && let Err(e) = writeln!(f, "{line}")
@@ -77,17 +224,90 @@ impl MergeLogger {
 
     pub fn log_summary(&mut self, line: &str) {
         if let Some(path) = &self.summary_path {
-            match OpenOptions::new().create(true).append(true).open(path) {
-                Ok(mut f) => {
-                    if let Err(e) = writeln!(f, "{line}") {
-                        tracing::warn!("Summary log write failed: {e}");
-                    }
+            let line = self.with_run_info(line);
+            if let Err(e) = append_line(path, &line) {
+                tracing::warn!("Summary log write failed: {e}");
+            }
+        }
+    }
+
+    /// Record that the merge was aborted before Claude produced a result, in both the event log
+    /// and the summary log.
+    ///
+    /// `estimated_cost_usd` is our own estimate from the tokens used so far, for when Claude
+    /// didn't report a cost.
+    pub fn log_abort(
+        &mut self,
+        reason: &str,
+        total_cost_usd: Option<f64>,
+        estimated_cost_usd: Option<f64>,
+    ) {
+        let line = serde_json::json!({
+            "type": "abort",
+            "reason": reason,
+            "total_cost_usd": total_cost_usd,
+            "estimated_cost_usd": estimated_cost_usd,
+        })
+        .to_string();
+        self.log_event(&line);
+        self.log_summary(&line);
+    }
+
+    /// Record that Claude deliberately left the conflict for a human, in both the event log and
+    /// the summary log.
+    pub fn log_deferred(&mut self, reason: &str, total_cost_usd: Option<f64>) {
+        let line = serde_json::json!({
+            "type": "deferred",
+            "reason": reason,
+            "total_cost_usd": total_cost_usd,
+        })
+        .to_string();
+        self.log_event(&line);
+        self.log_summary(&line);
+    }
+
+    /// Add `run_id`, `timestamp`, `claude_version`, and `session_id` fields to a JSON object
+    /// line, leaving anything else untouched.
+    fn with_run_info(&self, line: &str) -> String {
+        match serde_json::from_str::<serde_json::Value>(line) {
+            Ok(serde_json::Value::Object(mut object)) => {
+                object.insert("run_id".to_owned(), self.run_id.clone().into());
+                object.insert(
+                    "timestamp".to_owned(),
+                    jiff::Timestamp::now().to_string().into(),
+                );
+                if let Some(version) = &self.claude_version {
+                    object.insert("claude_version".to_owned(), version.clone().into());
                 }
-                Err(e) => {
-                    tracing::warn!("Failed to open summary log: {e}");
+                if let Some(session_id) = &self.session_id {
+                    object
+                        .entry("session_id")
+                        .or_insert_with(|| session_id.clone().into());
                 }
+                serde_json::Value::Object(object).to_string()
+            }
+            _ => line.to_owned(),
+        }
+    }
+}
+
+impl Drop for MergeLogger {
+    fn drop(&mut self) {
+        let Some(f) = &mut self.event_file else {
+            return;
+        };
+
+        if std::thread::panicking() {
+            let message = LAST_PANIC.lock().ok().and_then(|mut last| last.take());
+            let line = serde_json::json!({ "type": "panic", "message": message });
+            if let Err(e) = writeln!(f, "{line}") {
+                tracing::warn!("Failed to record panic in event log: {e}");
             }
         }
+
+        if let Err(e) = f.flush().and_then(|()| f.sync_all()) {
+            tracing::warn!("Failed to flush event log: {e}");
+        }
     }
 }
 
@@ -95,6 +315,21 @@ impl MergeLogger {
 mod tests {
     use super::*;
 
+    #[test]
+    fn catch_unwind_silently_skips_panic_hook() {
+        install_panic_hook();
+        let result = catch_unwind_silently(|| panic!("silenced panic"));
+        assert!(result.is_err());
+        assert!(
+            !LAST_PANIC
+                .lock()
+                .unwrap()
+                .as_deref()
+                .is_some_and(|panic| panic.contains("silenced panic"))
+        );
+        assert_eq!(catch_unwind_silently(|| 1).unwrap(), 1);
+    }
+
     #[test]
     fn sanitize_basic() {
         assert_eq!(sanitize_filepath("src/lib.rs"), "src_lib.rs");
@@ -126,6 +361,9 @@ mod tests {
 
         let event_file = File::create(&event_path).unwrap();
         let mut logger = MergeLogger {
+            run_id: new_run_id(),
+            claude_version: None,
+            session_id: None,
             event_file: Some(event_file),
             summary_path: Some(summary_path.clone()),
         };
@@ -149,4 +387,200 @@ mod tests {
         assert_eq!(summary_lines.len(), 1);
         assert!(summary_lines[0].contains("\"type\":\"result\""));
     }
+
+    #[test]
+    fn logger_flushes_on_panic() {
+        install_panic_hook();
+
+        let dir = tempfile::tempdir().unwrap();
+        let event_path = dir.path().join("events.jsonl");
+        let event_file = File::create(&event_path).unwrap();
+
+        let result = std::thread::spawn(move || {
+            let mut logger = MergeLogger {
+                run_id: new_run_id(),
+                claude_version: None,
+                session_id: None,
+                event_file: Some(event_file),
+                summary_path: None,
+            };
+            logger.log_event(r#"{"type":"assistant","message":{}}"#);
+            panic!("claude went off the rails");
+        })
+        .join();
+        assert!(result.is_err());
+
+        let events = fs::read_to_string(&event_path).unwrap();
+        let lines: Vec<&str> = events.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], r#"{"type":"assistant","message":{}}"#);
+
+        let panic: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(panic["type"], "panic");
+        assert!(
+            panic["message"]
+                .as_str()
+                .unwrap()
+                .contains("claude went off the rails")
+        );
+    }
+
+    #[test]
+    fn run_id_in_event_filename_and_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let run_id = new_run_id();
+
+        let mut logger = MergeLogger::in_dir(dir.path(), Some("src/lib.rs"), &run_id);
+        logger.set_claude_version(Some("2.0.14 (Claude Code)".to_owned()));
+        logger.log_summary(r#"{"type":"result","subtype":"success"}"#);
+        drop(logger);
+
+        let event_logs: Vec<String> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name != "summary.jsonl")
+            .collect();
+        assert_eq!(event_logs.len(), 1);
+        assert!(
+            event_logs[0].ends_with(&format!("_src_lib.rs_{run_id}.jsonl")),
+            "{event_logs:?}"
+        );
+
+        let summary = fs::read_to_string(dir.path().join("summary.jsonl")).unwrap();
+        let summary: serde_json::Value = serde_json::from_str(summary.trim()).unwrap();
+        assert_eq!(summary["run_id"], run_id.as_str());
+        assert_eq!(summary["claude_version"], "2.0.14 (Claude Code)");
+        assert!(
+            summary["timestamp"]
+                .as_str()
+                .unwrap()
+                .parse::<jiff::Timestamp>()
+                .is_ok()
+        );
+        assert_eq!(summary["type"], "result");
+    }
+
+    #[test]
+    fn aborted_run_logs_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let run_id = new_run_id();
+
+        let mut logger = MergeLogger::in_dir(dir.path(), Some("src/lib.rs"), &run_id);
+        logger.log_event(r#"{"type":"assistant","message":{}}"#);
+        logger.log_abort("claude exited without a result", None, Some(0.25));
+        drop(logger);
+
+        let summary = fs::read_to_string(dir.path().join("summary.jsonl")).unwrap();
+        let summary: serde_json::Value = serde_json::from_str(summary.trim()).unwrap();
+        assert_eq!(summary["type"], "abort");
+        assert_eq!(summary["reason"], "claude exited without a result");
+        assert_eq!(summary["total_cost_usd"], serde_json::Value::Null);
+        assert_eq!(summary["estimated_cost_usd"], 0.25);
+        assert_eq!(summary["run_id"], run_id.as_str());
+
+        let event_log = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| !path.ends_with("summary.jsonl"))
+            .unwrap();
+        let events = fs::read_to_string(event_log).unwrap();
+        assert_eq!(events.lines().count(), 2);
+        assert!(events.lines().last().unwrap().contains(r#""type":"abort""#));
+    }
+
+    #[test]
+    fn session_id_in_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = MergeLogger::in_dir(dir.path(), None, &new_run_id());
+        logger.set_session_id("5d2f6a10-8c1e-4b7a-9f3d-2e6c1a0b9d84");
+        logger.log_abort("claude timed out after 10m", None, None);
+        // A session ID in the line itself wins.
+        logger.log_summary(r#"{"type":"result","session_id":"9a7c3e21"}"#);
+        drop(logger);
+
+        let summary = fs::read_to_string(dir.path().join("summary.jsonl")).unwrap();
+        let lines = summary
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines[0]["type"], "abort");
+        assert_eq!(
+            lines[0]["session_id"],
+            "5d2f6a10-8c1e-4b7a-9f3d-2e6c1a0b9d84"
+        );
+        assert_eq!(lines[1]["session_id"], "9a7c3e21");
+    }
+
+    #[test]
+    fn concurrent_summary_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let summary_path = dir.path().join("summary.jsonl");
+
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let summary_path = summary_path.clone();
+                scope.spawn(move || {
+                    let mut logger = MergeLogger {
+                        run_id: new_run_id(),
+                        claude_version: None,
+                        session_id: None,
+                        event_file: None,
+                        summary_path: Some(summary_path),
+                    };
+                    let padding = "x".repeat(8192);
+                    for i in 0..50 {
+                        logger.log_summary(
+                            &serde_json::json!({
+                                "type": "result",
+                                "thread": thread,
+                                "i": i,
+                                "padding": padding,
+                            })
+                            .to_string(),
+                        );
+                    }
+                });
+            }
+        });
+
+        let summary = fs::read_to_string(&summary_path).unwrap();
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines.len(), 8 * 50);
+        for line in lines {
+            let line: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(line["type"], "result");
+        }
+    }
+
+    #[test]
+    fn prune_old_event_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "2026-01-01T09-00-00_src_lib.rs_01ABC.jsonl",
+            "2026-03-01T09-00-00_src_main.rs_01DEF.jsonl",
+            "summary.jsonl",
+            "notes.txt",
+            "not-a-timestamp.jsonl",
+        ] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let removed = prune_event_logs(dir.path(), jiff::civil::date(2026, 2, 1).at(0, 0, 0, 0));
+        assert_eq!(removed, 1);
+
+        let mut remaining = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(
+            remaining,
+            [
+                "2026-03-01T09-00-00_src_main.rs_01DEF.jsonl",
+                "not-a-timestamp.jsonl",
+                "notes.txt",
+                "summary.jsonl",
+            ]
+        );
+    }
 }
@@ -2,18 +2,40 @@ use clap::Parser;
 use command_error::ChildExt;
 use command_error::CommandExt;
 use command_error::Utf8ProgramAndArgs;
+use indicatif::ProgressBar;
+use miette::Context;
 use miette::IntoDiagnostic;
+use miette::miette;
 use owo_colors::OwoColorize;
-use std::collections::BTreeSet;
+use owo_colors::Stream::Stderr;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::IsTerminal;
 use std::io::Write;
-use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::process::{Command, ExitCode, Stdio};
+use std::time::Duration;
 use tracing::level_filters::LevelFilter;
 
+mod attribution;
 mod claude_json;
+mod claude_version;
+mod config;
+mod diff;
+mod eval;
+mod git;
 mod install;
+mod interrupt;
+mod lockfile;
 mod logging;
+mod markers;
+mod pricing;
+mod progress;
+mod stats;
+mod validate;
+mod watchdog;
+mod webhook;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -24,17 +46,84 @@ mod logging;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// When to use colors. `auto` uses colors when stderr is a terminal and `NO_COLOR` isn't set
+    #[arg(long, global = true, value_enum, default_value_t)]
+    color: ColorChoice,
+
+    /// Don't use colors or Markdown rendering; the same as `--color never`
+    #[arg(long, global = true, conflicts_with = "color")]
+    no_color: bool,
+
+    /// The same as `--color always`, kept for `merge --force-color` in existing Git configs
+    #[arg(long, global = true, hide = true, conflicts_with_all = ["color", "no_color"])]
+    force_color: bool,
+}
+
+impl Cli {
+    fn color(&self) -> ColorChoice {
+        if self.no_color {
+            ColorChoice::Never
+        } else if self.force_color {
+            ColorChoice::Always
+        } else {
+            self.color
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// How to report the merge result on stdout.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OutputFormat {
+    /// Show Claude's progress and the result on stderr.
+    #[default]
+    Pretty,
+    /// Print Claude's cost, durations, and token usage as a JSON object, without showing
+    /// Claude's progress on stderr.
+    Json,
+}
+
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                // See: https://no-color.org/
+                std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && std::io::stderr().is_terminal()
+            }
+        }
+    }
 }
 
 #[derive(clap::Subcommand, Debug)]
 enum Commands {
     /// Resolve a merge conflict using Claude
-    Merge(MergeArgs),
+    Merge(Box<MergeArgs>),
     /// Install `claude-mergetool` as a merge tool for Git or jj.
     Install(install::InstallArgs),
+    /// Remove the configuration written by `install`.
+    Uninstall(install::UninstallArgs),
+    /// Write a commented config file template.
+    GenerateConfig(config::GenerateConfigArgs),
+    /// Print a JSON Schema for the config file, for editor completion and validation.
+    ConfigSchema,
+    /// Summarize cost and usage from the merge log.
+    Stats(stats::StatsArgs),
+    /// Resolve a directory of example conflicts and report how often Claude gets them right.
+    Eval(eval::EvalArgs),
 }
 
-#[derive(clap::Args, Debug)]
+#[derive(clap::Args, Clone, Debug)]
 struct MergeArgs {
     /// Git merge driver mode (writes result to `<left>` path)
     #[arg(long)]
@@ -46,6 +135,13 @@ struct MergeArgs {
     left: PathBuf,
     /// Right version (theirs / incoming)
     right: PathBuf,
+    /// Additional versions to merge, for octopus merges
+    #[arg(value_name = "SIDES")]
+    extra_sides: Vec<PathBuf>,
+
+    /// Use this file as the base instead of `<BASE>`, e.g. a better merge base computed by hand
+    #[arg(long, value_name = "PATH")]
+    base_override: Option<PathBuf>,
 
     /// Output file path (jj mode)
     #[arg(short = 'o', long)]
@@ -54,20 +150,202 @@ struct MergeArgs {
     /// Ancestor conflict label
     #[arg(short = 's')]
     ancestor_label: Option<String>,
-    /// Left/ours conflict label
-    #[arg(short = 'x', default_value = "ours")]
-    left_label: String,
-    /// Right/theirs conflict label
-    #[arg(short = 'y', default_value = "theirs")]
-    right_label: String,
+    /// Left/ours conflict label [default: ours]
+    #[arg(short = 'x')]
+    left_label: Option<String>,
+    /// Right/theirs conflict label [default: theirs]
+    #[arg(short = 'y')]
+    right_label: Option<String>,
+
+    /// File with the ancestor, left, and right labels on separate lines, used for any of
+    /// `-s`/`-x`/`-y` that aren't given
+    #[arg(long)]
+    labels_file: Option<PathBuf>,
 
     /// Original file path
     #[arg(short = 'p')]
     filepath: Option<String>,
 
+    /// The file's path on the left side, if it was renamed
+    #[arg(long, value_name = "PATH")]
+    left_path: Option<String>,
+
+    /// The file's path on the right side, if it was renamed
+    #[arg(long, value_name = "PATH")]
+    right_path: Option<String>,
+
     /// Conflict marker size
     #[arg(short = 'l')]
     marker_size: Option<u32>,
+
+    /// Reject the resolution if a JSON, YAML, or TOML file doesn't parse afterwards
+    #[arg(long)]
+    apply_only_if_valid_json: bool,
+
+    /// Show Claude's resolution as a diff against the left side and ask on the terminal before
+    /// keeping it, and ask for guidance if Claude can't decide; skipped if there's no terminal
+    #[arg(long)]
+    interactive: bool,
+
+    /// Show what Claude changed relative to the left side once the merge is done
+    #[arg(long)]
+    show_diff: bool,
+
+    /// Validate files with extension `EXT` as `FORMAT` (json, yaml, or toml); repeatable
+    #[arg(long, value_name = "EXT=FORMAT", value_parser = validate::parse_extension_mapping)]
+    validate_extension: Vec<(String, validate::Format)>,
+
+    /// Skip `--add-dir` grants for directories already covered by a granted ancestor
+    #[arg(long)]
+    dedupe_add_dir: bool,
+
+    /// Include `git notes` attached to the conflicting commits in the prompt as resolution hints
+    #[arg(long)]
+    prompt_from_git_notes: bool,
+
+    /// Show Claude the conflicting regions of the output file with the conflict markers already
+    /// parsed out
+    #[arg(long)]
+    strip_markers_first: bool,
+
+    /// Show Claude what each side changed since this commit, instead of only the merge base
+    #[arg(long, value_name = "REF")]
+    since_commit: Option<String>,
+
+    /// Config file to use instead of the default; overrides `$CLAUDE_MERGETOOL_CONFIG`
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// The model for Claude to use (e.g. `sonnet` or `opus`), overriding the config file's `model`
+    /// and any `--model` in its `extra_args`
+    #[arg(long)]
+    model: Option<String>,
+
+    /// Continue an earlier Claude session, like one that timed out or that you interrupted, by
+    /// the ID shown at the end of its output
+    #[arg(long, value_name = "SESSION_ID")]
+    resume: Option<String>,
+
+    /// Set an environment variable for `claude`; repeatable
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_env_var)]
+    claude_env: Vec<(String, String)>,
+
+    /// Resolve lockfiles (`Cargo.lock`, `package-lock.json`, `flake.lock`) by keeping the left
+    /// side and regenerating them instead of calling Claude; requires `-p`
+    #[arg(long)]
+    resolve_binary_with_rules: bool,
+
+    /// Send the file contents to `claude` inline on stdin and write its reply to the output,
+    /// for sandboxes where `claude` can't read or write the temporary files
+    #[arg(long)]
+    claude_stdin: bool,
+
+    /// Show Claude's extended thinking, if it's enabled
+    #[arg(long)]
+    show_thinking: bool,
+
+    /// Show the first few lines of the output of each of Claude's tool calls
+    #[arg(long)]
+    show_tool_results: bool,
+
+    /// Only show the merge result, not Claude's messages and tool calls
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Don't resolve symlinks in the temp directory before replacing it with `$TMPDIR` in
+    /// Claude's output, for sandboxes where that fails or finds the wrong path
+    #[arg(long)]
+    no_canonicalize_tmpdir: bool,
+
+    /// Also replace this path prefix with `$TMPDIR` in Claude's output; repeatable
+    #[arg(long, value_name = "PATH")]
+    tmpdir_prefix: Vec<String>,
+
+    /// Wrap Claude's messages at this many columns instead of the terminal width
+    #[arg(long, value_name = "COLUMNS")]
+    wrap_width: Option<usize>,
+
+    /// Don't write event logs or the summary log, even if the config file enables them
+    #[arg(long)]
+    no_log: bool,
+
+    /// Print the command that would be run instead of running it
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Run Claude in plan mode, so it describes the resolution without editing any files, and
+    /// leave the conflict unresolved
+    #[arg(long)]
+    dry_run_claude: bool,
+
+    /// Succeed even if the resolved file still contains conflict markers, for intentionally
+    /// partial resolutions
+    #[arg(long)]
+    allow_markers: bool,
+
+    /// Copy the output file to `<output>.orig` before Claude edits it
+    #[arg(long)]
+    backup: bool,
+
+    /// If Claude finishes but leaves conflict markers, or a file `--apply-only-if-valid-json`
+    /// rejects, run it once more and ask it to fix them
+    #[arg(long)]
+    retry_on_markers: bool,
+
+    /// What to tell Claude when running it again for `--retry-on-markers`; `{kind}` is replaced
+    /// with `markers` or `validation`, and `{reason}` with what went wrong
+    #[arg(long, value_name = "TEMPLATE")]
+    retry_prompt: Option<String>,
+
+    /// Run Claude again up to this many times if it fails with a transient error, like being rate
+    /// limited, waiting longer before each attempt
+    #[arg(long, value_name = "N", alias = "max-retries")]
+    retries: Option<u32>,
+
+    /// Ask Claude which side it took for each conflicting region, and show a summary afterwards
+    #[arg(long)]
+    attribution: bool,
+
+    /// Fail the merge if Claude searches the web
+    #[arg(long)]
+    fail_on_web_search: bool,
+
+    /// Write JSON progress events to stdout, for wrapper UIs
+    #[arg(long)]
+    progress_json: bool,
+
+    /// Kill Claude and fail the merge if it takes longer than this (e.g. `10m`)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+
+    /// Kill Claude and fail the merge if it goes this long without output (e.g. `2m`)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    idle_timeout: Option<Duration>,
+
+    /// Fail the merge if Claude spends more than this many US dollars
+    #[arg(long, value_name = "USD")]
+    max_cost: Option<f64>,
+
+    /// POST a JSON summary of the merge to this URL when it finishes
+    #[arg(long, value_name = "URL")]
+    result_webhook: Option<String>,
+
+    /// Output format for the merge result on stdout
+    #[arg(long, value_enum, default_value_t)]
+    output_format: OutputFormat,
+
+    /// Print Claude's final result on stdout; the rest of its output stays on stderr
+    #[arg(long, conflicts_with = "output_format")]
+    summary_line_to_stdout: bool,
+
+    /// Set the resolved file's mode to this octal value (e.g. `0755`) after a successful merge;
+    /// Unix only
+    #[arg(long, value_name = "OCTAL", value_parser = parse_mode)]
+    output_permissions: Option<u32>,
+
+    /// Where `--interactive` asks questions, instead of the terminal; for tests.
+    #[arg(skip)]
+    terminal: Option<PathBuf>,
 }
 
 impl MergeArgs {
@@ -81,206 +359,3615 @@ impl MergeArgs {
         }
     }
 
+    fn max_cost_usd(&self, config: &config::Config) -> Option<f64> {
+        self.max_cost.or(config.max_cost_usd())
+    }
+
+    /// Warn if any version has a line longer than `max_line_length`, like a minified file.
+    fn warn_about_huge_lines(&self, max_line_length: usize) -> miette::Result<()> {
+        if max_line_length == 0 {
+            return Ok(());
+        }
+        let inputs = [self.base()?, self.left.as_path(), self.right.as_path()]
+            .into_iter()
+            .chain(self.extra_sides.iter().map(PathBuf::as_path));
+        for path in inputs {
+            // Unreadable files are reported when Claude tries to read them.
+            if let Ok(contents) = std::fs::read_to_string(path)
+                && has_huge_line(&contents, max_line_length)
+            {
+                tracing::warn!(
+                    "{} has a line longer than {max_line_length} characters; if it's minified, \
+                    Claude may spend a lot of tokens on it and struggle to merge it",
+                    path.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The base version, after applying `--base-override`.
+    fn base(&self) -> miette::Result<&Path> {
+        match &self.base_override {
+            Some(path) => {
+                std::fs::read_to_string(path)
+                    .into_diagnostic()
+                    .wrap_err_with(|| {
+                        format!(
+                            "Base override {} isn't a readable UTF-8 file",
+                            path.display()
+                        )
+                    })?;
+                Ok(path)
+            }
+            None => Ok(&self.base),
+        }
+    }
+
+    /// Check that every version of the file is readable and that the output can be written, so
+    /// a mistyped path fails here instead of partway through Claude's run.
+    fn validate_paths(&self) -> miette::Result<()> {
+        let inputs = [
+            ("Base", self.base()?),
+            ("Left", self.left.as_path()),
+            ("Right", self.right.as_path()),
+        ]
+        .into_iter()
+        .chain(
+            self.extra_sides
+                .iter()
+                .map(|path| ("Extra", path.as_path())),
+        );
+        for (side, path) in inputs {
+            std::fs::File::open(path)
+                .into_diagnostic()
+                .wrap_err_with(|| {
+                    format!(
+                        "{side} version {} doesn't exist or isn't readable",
+                        path.display()
+                    )
+                })?;
+        }
+
+        let output = self.output_path()?;
+        if let Some(parent) = output.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)
+                .into_diagnostic()
+                .wrap_err_with(|| {
+                    format!(
+                        "Failed to create {} for the output {}",
+                        parent.display(),
+                        output.display()
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
     fn filepath(&self) -> &str {
         self.filepath.as_deref().unwrap_or("unknown file")
     }
 
-    fn command(&self) -> miette::Result<Command> {
-        if let Some(filepath) = &self.filepath {
-            eprintln!(
-                "{}",
-                format!("Resolving merge conflict in {}", filepath.underline())
-                    .bold()
-                    .green()
-            );
+    /// The file's paths on the left and right sides, if `--left-path` or `--right-path` says it
+    /// was renamed.
+    fn renamed_paths(&self) -> Option<(&str, &str)> {
+        let left = self.left_path.as_deref().or(self.filepath.as_deref())?;
+        let right = self.right_path.as_deref().or(self.filepath.as_deref())?;
+        (left != right).then_some((left, right))
+    }
+
+    fn labels(&self) -> miette::Result<Labels> {
+        let [mut ancestor, mut left, mut right] = Default::default();
+        if let Some(path) = &self.labels_file {
+            let contents = std::fs::read_to_string(path)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to read labels file {}", path.display()))?;
+            [ancestor, left, right] = parse_labels_file(&contents);
         }
 
-        let system_prompt = format!(
+        Ok(Labels {
+            ancestor: self.ancestor_label.clone().or(ancestor),
+            left: self
+                .left_label
+                .clone()
+                .or(left)
+                .unwrap_or_else(|| "ours".to_owned()),
+            right: self
+                .right_label
+                .clone()
+                .or(right)
+                .unwrap_or_else(|| "theirs".to_owned()),
+        })
+    }
+
+    fn command(&self, config: &config::Config) -> miette::Result<Command> {
+        self.command_with_note(config, None)
+    }
+
+    /// The agent command, with `note` appended to the user prompt, e.g. to explain why we're
+    /// running it again.
+    fn command_with_note(
+        &self,
+        config: &config::Config,
+        note: Option<&str>,
+    ) -> miette::Result<Command> {
+        let labels = self.labels()?;
+        let base = self.base()?;
+        // Add/add conflicts have no common ancestor.
+        let base = (!is_missing_base(base)).then_some(base);
+
+        let (provided, deliver) = if self.claude_stdin {
+            (
+                "inline in the prompt",
+                "reply with the complete resolved file between `<resolved>` and `</resolved>` tags",
+            )
+        } else {
+            (
+                "as temporary files",
+                "write a resolved version to the output path",
+            )
+        };
+
+        let (count, capitalized_count) =
+            match usize::from(base.is_some()) + 2 + self.extra_sides.len() {
+                2 => ("two".to_owned(), "Two".to_owned()),
+                3 => ("three".to_owned(), "Three".to_owned()),
+                count => (count.to_string(), count.to_string()),
+            };
+        let mut sides = base
+            .map(|_| format!("the base ({})", labels.base()))
+            .into_iter()
+            .chain([
+                format!("left ({})", labels.left),
+                format!("right ({})", labels.right),
+            ])
+            .chain((0..self.extra_sides.len()).map(|i| format!("side {}", i + 3)))
+            .collect::<Vec<_>>();
+        let sides = match sides.len() {
+            2 => sides.join(" and "),
+            _ => {
+                let last = sides.pop().expect("there are always at least two sides");
+                format!("{}, and {last}", sides.join(", "))
+            }
+        };
+        let read = match count.as_str() {
+            "two" => "both".to_owned(),
+            count => format!("all {count}"),
+        };
+        let versions_provided = match base {
+            Some(_) => format!(
+                "{capitalized_count} versions of the file are provided {provided}: {sides}. \
+                 Read {read}, understand what each side changed relative to the base, \
+                 and {deliver}.",
+            ),
+            None => format!(
+                "{capitalized_count} versions of the file are provided {provided}: {sides}. \
+                 There is no common ancestor because both sides added the file independently. \
+                 Read {read}, understand what each side added, and {deliver}.",
+            ),
+        };
+
+        let mut system_prompt = format!(
             "You are resolving a merge conflict in `{}`. \
              Your working directory is the root of the repository, so you can browse and edit \
              other files if needed (e.g. if code moved between files).\n\n\
-             Three versions of the file are provided as temporary files: \
-             the base (common ancestor), left ({}), and right ({}). \
-             Read all three, understand what each side changed relative to the base, \
-             and write a resolved version to the output path. \
+             {versions_provided} \
              If changes are compatible, merge them cleanly. \
-             If they genuinely conflict, use your best judgment and explain your reasoning.",
+             If they genuinely conflict, use your best judgment and explain your reasoning. \
+             If the conflict is so ambiguous that only a human can decide, leave the conflict \
+             markers in place and end your reply with a line starting with `{}` explaining why.",
             self.filepath(),
-            self.left_label,
-            self.right_label,
+            markers::DEFERRAL_SENTINEL,
         );
 
-        let user_prompt = format!(
+        if self.prompt_from_git_notes {
+            let notes = git::conflict_notes(Path::new("."));
+            if !notes.is_empty() {
+                system_prompt.push_str(
+                    "\n\nThe authors of the conflicting commits left these notes \
+                     with guidance for resolving the conflict:",
+                );
+                for (rev, note) in notes {
+                    system_prompt.push_str(&format!("\n\n{rev}:\n{note}"));
+                }
+            }
+        }
+
+        if let Some(since) = &self.since_commit {
+            let filepath = self
+                .filepath
+                .as_deref()
+                .ok_or_else(|| miette!("--since-commit requires the file path (`-p`)"))?;
+            let diffs = git::conflict_diffs_since(Path::new("."), since, filepath)
+                .wrap_err_with(|| format!("Failed to diff {filepath} since {since}"))?;
+            system_prompt.push_str(&format!(
+                "\n\nHere's what each side changed in `{filepath}` since {since}:"
+            ));
+            for (rev, diff) in diffs {
+                if diff.is_empty() {
+                    system_prompt.push_str(&format!("\n\n{rev}: no changes"));
+                } else {
+                    system_prompt.push_str(&format!("\n\n{rev}:\n```diff\n{diff}```"));
+                }
+            }
+        }
+
+        if self.strip_markers_first {
+            let output = self.output_path()?;
+            let marker_size = self.marker_size.unwrap_or(markers::DEFAULT_MARKER_SIZE);
+            match std::fs::read_to_string(output) {
+                Ok(contents) => {
+                    if let Some(stripped) = markers::strip_markers(&contents, marker_size) {
+                        system_prompt.push_str(&format!(
+                            "\n\nThese are the conflicting regions of the file, with the conflict \
+                             markers removed:\n\n{stripped}"
+                        ));
+                    }
+                }
+                Err(err) => {
+                    tracing::debug!("Not stripping markers from {}: {err}", output.display());
+                }
+            }
+        }
+
+        if let Some((left_path, right_path)) = self.renamed_paths() {
+            system_prompt.push_str(&format!(
+                "\n\nThe file was renamed: it's `{left_path}` on the left ({}) and \
+                 `{right_path}` on the right ({}). Apply each side's changes to the merged file \
+                 regardless of its name, and check whether other files refer to it by its old \
+                 name.",
+                labels.left, labels.right,
+            ));
+        }
+
+        if !self.extra_sides.is_empty() {
+            system_prompt.push_str(&format!(
+                "\n\nThis is an octopus merge: there are {} more sides after the right side, \
+                 and the resolution should include the changes from all of them.",
+                self.extra_sides.len()
+            ));
+        }
+
+        if self.attribution {
+            system_prompt.push_str("\n\n");
+            system_prompt.push_str(&attribution::prompt());
+        }
+
+        config.append_system_prompt(&mut system_prompt);
+        let filepath = self
+            .filepath
+            .as_deref()
+            .map_or(self.output_path()?, Path::new);
+        if let Some(extra) = config.extension_prompt(filepath) {
+            system_prompt.push_str("\n\n");
+            system_prompt.push_str(extra);
+        }
+
+        if self.dry_run_claude {
+            system_prompt.push_str(
+                "\n\nThis is a dry run: don't write or edit any files. Instead, describe how you \
+                 would resolve the conflict, including the exact edits you would make.",
+            );
+        }
+
+        let mut versions = String::new();
+        if let Some(base) = base {
+            versions.push_str(&format!("- Base ({}): {}\n", labels.base(), base.display()));
+        }
+        versions.push_str(&format!(
+            "- Left ({}): {}\n\
+             - Right ({}): {}\n",
+            labels.left,
+            self.left.display(),
+            labels.right,
+            self.right.display(),
+        ));
+        for (i, side) in self.extra_sides.iter().enumerate() {
+            versions.push_str(&format!("- Side {}: {}\n", i + 3, side.display()));
+        }
+        let mut user_prompt = format!(
             "Resolve the merge conflict in `{}`.\n\n\
-             Read these three versions of the file:\n\
-             - Base (common ancestor): {}\n\
-             - Left ({}): {}\n\
-             - Right ({}): {}\n\n\
+             Read these {count} versions of the file:\n\
+             {versions}\n\
              Write the resolved file to: {}",
             self.filepath(),
-            self.base.display(),
-            self.left_label,
-            self.left.display(),
-            self.right_label,
-            self.right.display(),
             self.output_path()?.display(),
         );
+        if let Some(marker_size) = self.marker_size {
+            user_prompt.push_str(&format!(
+                "\n\nConflict markers in these files are {marker_size} characters wide \
+                 (e.g. `{}`).",
+                "<".repeat(marker_size as usize),
+            ));
+        }
+        if let Some(note) = note {
+            user_prompt.push_str("\n\n");
+            user_prompt.push_str(note);
+        }
 
         // Collect unique parent dirs from all temp file paths and grant
         // Read/Write/Edit access so Claude can work with them without prompts.
-        let temp_dirs: BTreeSet<_> = [
-            self.base.as_path(),
-            self.left.as_path(),
-            self.right.as_path(),
-            self.output_path()?,
-        ]
-        .iter()
-        .filter_map(|p| p.parent().filter(|p| *p != ""))
-        .collect();
+        let mut temp_dirs: BTreeSet<_> = base
+            .into_iter()
+            .chain([
+                self.left.as_path(),
+                self.right.as_path(),
+                self.output_path()?,
+            ])
+            .chain(self.extra_sides.iter().map(PathBuf::as_path))
+            .filter_map(|p| p.parent().filter(|p| *p != ""))
+            .collect();
+
+        if self.dedupe_add_dir {
+            temp_dirs = dedupe_dirs(temp_dirs);
+        }
+
+        if let config::Backend::Custom {
+            program,
+            args_template,
+        } = config.backend()
+        {
+            if self.claude_stdin {
+                return Err(miette!("--claude-stdin only works with the Claude backend"));
+            }
+            if self.dry_run_claude {
+                return Err(miette!(
+                    "--dry-run-claude only works with the Claude backend"
+                ));
+            }
+            if self.resume.is_some() {
+                return Err(miette!("--resume only works with the Claude backend"));
+            }
+
+            let base = self.base()?.display().to_string();
+            let left = self.left.display().to_string();
+            let right = self.right.display().to_string();
+            let output = self.output_path()?.display().to_string();
+            let placeholders = [
+                ("base", base.as_str()),
+                ("left", &left),
+                ("right", &right),
+                ("output", &output),
+                ("system_prompt", &system_prompt),
+                ("user_prompt", &user_prompt),
+            ];
+
+            let mut command = Command::new(program);
+            command
+                .args(
+                    args_template
+                        .iter()
+                        .map(|arg| substitute_placeholders(arg, &placeholders)),
+                )
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped());
+            command.envs(config.claude_env());
+            command.envs(self.claude_env.iter().map(|(key, value)| (key, value)));
+
+            tracing::debug!("Backend command: {}", Utf8ProgramAndArgs::from(&command));
 
-        let mut command = Command::new("claude");
+            return Ok(command);
+        }
+
+        let mut command = Command::new(config.claude_binary());
 
         command
             .arg("--print")
             .arg("--verbose")
             .arg("--output-format=stream-json")
-            .arg("--permission-mode=acceptEdits")
+            .arg(format!(
+                "--permission-mode={}",
+                // Plan mode doesn't let Claude edit anything.
+                if self.dry_run_claude {
+                    "plan"
+                } else {
+                    config.permission_mode()
+                }
+            ))
             .arg("--append-system-prompt")
             .arg(&system_prompt)
-            .arg(user_prompt)
-            .stdin(Stdio::null())
             .stdout(Stdio::piped());
 
-        for dir in &temp_dirs {
-            let dir_display = dir.display();
-            tracing::debug!("Granting access to {dir_display}");
-            command.arg("--add-dir").arg(*dir);
+        if let Some(model) = self.model.as_deref().or(config.model()) {
+            command.arg("--model").arg(model);
+        }
+
+        if let Some(session_id) = &self.resume {
+            command.arg("--resume").arg(session_id);
+        }
+
+        if !config.allowed_tools().is_empty() {
+            command
+                .arg("--allowedTools")
+                .arg(config.allowed_tools().join(","));
+        }
+        if !config.disallowed_tools().is_empty() {
+            command
+                .arg("--disallowedTools")
+                .arg(config.disallowed_tools().join(","));
+        }
+
+        if let Some(max_cost) = self.max_cost_usd(config) {
+            // Claude stops on its own once it's over budget, but only checks between turns, so we
+            // check the final cost ourselves too.
+            command.arg("--max-budget-usd").arg(max_cost.to_string());
+        }
+
+        if self.claude_stdin {
+            // The prompt is written to stdin by `run`, and Claude doesn't need the temp dirs.
+            command.stdin(Stdio::piped());
+        } else {
+            command.arg(user_prompt).stdin(Stdio::null());
+
+            for dir in &temp_dirs {
+                let dir_display = dir.display();
+                tracing::debug!("Granting access to {dir_display}");
+                command.arg("--add-dir").arg(*dir);
+            }
+        }
+
+        if self.model.is_some() {
+            // `--model` wins over a model in `extra_args` too.
+            command.args(without_model_args(config.extra_args()));
+        } else {
+            command.args(config.extra_args());
         }
 
+        // Flags win over the config file.
+        command.envs(config.claude_env());
+        command.envs(self.claude_env.iter().map(|(key, value)| (key, value)));
+
         tracing::debug!("Claude command: {}", Utf8ProgramAndArgs::from(&command));
 
         Ok(command)
     }
 
-    fn run(&self) -> miette::Result<()> {
-        let mut child = self.command()?.spawn_checked()?;
-        let stdout = child
-            .child_mut()
-            .stdout
-            .take()
-            .expect("claude piped stdout should have a stdout field");
-        let reader = BufReader::new(stdout);
-
-        let writer = claude_json::ClaudeEventWriter::new()?;
-        let mut logger = logging::MergeLogger::new(self.filepath.as_deref());
-
-        for line in reader.lines() {
-            match line {
-                Ok(line) => {
-                    logger.log_event(&line);
-                    if let Some(event) = writer.display(&line) {
-                        if event.is_result() {
-                            logger.log_summary(&line);
-                        }
-                        write!(std::io::stderr().lock(), "{event}").into_diagnostic()?;
+    /// The user prompt for `--claude-stdin`, with the contents of each version inline.
+    fn inline_prompt(&self) -> miette::Result<String> {
+        let labels = self.labels()?;
+        let read = |path: &Path| {
+            std::fs::read_to_string(path)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to read {}", path.display()))
+                .map(|mut contents| {
+                    if !contents.ends_with('\n') {
+                        contents.push('\n');
                     }
-                }
-                Err(err) => {
-                    tracing::debug!("{err}");
-                }
-            }
+                    contents
+                })
+        };
+
+        let mut prompt = format!("Resolve the merge conflict in `{}`.\n\n", self.filepath());
+        let base = self.base()?;
+        if !is_missing_base(base) {
+            prompt.push_str(&format!(
+                "Base ({}):\n<base>\n{}</base>\n\n",
+                labels.base(),
+                read(base)?
+            ));
         }
+        prompt.push_str(&format!(
+            "Left ({}):\n<left>\n{}</left>\n\n\
+             Right ({}):\n<right>\n{}</right>\n\n",
+            labels.left,
+            read(&self.left)?,
+            labels.right,
+            read(&self.right)?,
+        ));
+        for (i, side) in self.extra_sides.iter().enumerate() {
+            let n = i + 3;
+            prompt.push_str(&format!(
+                "Side {n}:\n<side{n}>\n{}</side{n}>\n\n",
+                read(side)?
+            ));
+        }
+        prompt.push_str(
+            "Reply with the complete resolved file between `<resolved>` and `</resolved>` tags.",
+        );
+        Ok(prompt)
+    }
 
-        child.wait_checked()?;
+    fn run(&self, color: bool) -> miette::Result<ExitCode> {
+        interrupt::install_handler();
+        let config = config::load_config(self.config.as_deref())?;
+        // A dry run doesn't change anything, so there's nothing to report.
+        let webhook_url = self
+            .result_webhook
+            .as_deref()
+            .or(config.result_webhook())
+            .filter(|_| !self.dry_run && !self.dry_run_claude);
 
-        Ok(())
+        let mut progress = if self.progress_json {
+            progress::ProgressReporter::new(Box::new(std::io::stdout()))
+        } else {
+            progress::ProgressReporter::disabled()
+        };
+        progress.emit(&progress::Progress::Started {
+            file: self.filepath().to_owned(),
+        });
+        let start = std::time::Instant::now();
+        let mut report = webhook::MergeReport {
+            filepath: self.filepath().to_owned(),
+            ..Default::default()
+        };
+        let result = self.resolve(&config, color, &mut progress, &mut report);
+        let success = matches!(result, Ok(Outcome::Resolved));
+        progress.emit(&progress::Progress::Done { success });
+        if let Some(url) = webhook_url {
+            report.resolved = success;
+            report.duration_ms = start.elapsed().as_millis();
+            webhook::post(url, &report);
+        }
+        result.map(|outcome| outcome.exit_code())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use command_error::Utf8ProgramAndArgs;
-    use expect_test::expect;
+    fn resolve(
+        &self,
+        config: &config::Config,
+        color: bool,
+        progress: &mut progress::ProgressReporter,
+        report: &mut webhook::MergeReport,
+    ) -> miette::Result<Outcome> {
+        self.validate_paths()?;
+        if self.resolve_binary_with_rules
+            && let Some(filepath) = self.filepath.as_deref().map(Path::new)
+            && let Some(lockfile) = lockfile::Lockfile::detect(filepath)
+        {
+            if self.dry_run || self.dry_run_claude {
+                println!("{}", Utf8ProgramAndArgs::from(&lockfile.command(filepath)));
+                // `--dry-run-claude` leaves the conflict unresolved, so it mustn't look resolved.
+                return Ok(if self.dry_run_claude {
+                    Outcome::DryRun
+                } else {
+                    Outcome::Resolved
+                });
+            }
+            lockfile.resolve(&self.left, filepath, self.output_path()?)?;
+            self.set_output_permissions()?;
+            return Ok(Outcome::Resolved);
+        }
 
-    #[test]
-    fn command_git_mode() {
-        let args = MergeArgs {
-            git_merge_driver: true,
-            base: PathBuf::from("/tmp/base.txt"),
-            left: PathBuf::from("/tmp/left.txt"),
-            right: PathBuf::from("/tmp/right.txt"),
-            output: None,
-            ancestor_label: None,
-            left_label: "ours".to_string(),
-            right_label: "theirs".to_string(),
-            filepath: Some("src/lib.rs".to_string()),
-            marker_size: None,
-        };
-        let command = args.command().unwrap();
-        let displayed: Utf8ProgramAndArgs = (&command).into();
-        expect![[r#"
-            claude --print --verbose '--output-format=stream-json' '--permission-mode=acceptEdits' --append-system-prompt 'You are resolving a merge conflict in `src/lib.rs`. Your working directory is the root of the repository, so you can browse and edit other files if needed (e.g. if code moved between files).
+        if let Some(outcome) = self.regenerate(config)? {
+            return Ok(outcome);
+        }
+        if self.dry_run {
+            println!("{}", Utf8ProgramAndArgs::from(&self.command(config)?));
+            return Ok(Outcome::Resolved);
+        }
+        if let Some(outcome) = self.merge_without_claude()? {
+            return Ok(outcome);
+        }
+        self.warn_about_huge_lines(config.max_line_length())?;
 
-            Three versions of the file are provided as temporary files: the base (common ancestor), left (ours), and right (theirs). Read all three, understand what each side changed relative to the base, and write a resolved version to the output path. If changes are compatible, merge them cleanly. If they genuinely conflict, use your best judgment and explain your reasoning.' 'Resolve the merge conflict in `src/lib.rs`.
+        if self.interactive {
+            self.resolve_staged(config, color, progress, report)
+        } else {
+            self.resolve_with_claude(config, color, progress, report)
+        }
+    }
 
-            Read these three versions of the file:
-            - Base (common ancestor): /tmp/base.txt
-            - Left (ours): /tmp/left.txt
-            - Right (theirs): /tmp/right.txt
+    /// For `--interactive`, have Claude write its resolution to a copy of the output, which
+    /// only replaces the output once the user accepts it. Until then, and if we're killed while
+    /// asking, the output is left alone.
+    fn resolve_staged(
+        &self,
+        config: &config::Config,
+        color: bool,
+        progress: &mut progress::ProgressReporter,
+        report: &mut webhook::MergeReport,
+    ) -> miette::Result<Outcome> {
+        let output = self.output_path()?;
+        let staging = tempfile::tempdir()
+            .into_diagnostic()
+            .wrap_err("Failed to create a temporary directory")?;
+        let staged = staging
+            .path()
+            .join(output.file_name().unwrap_or("output".as_ref()));
+        let original_output = std::fs::read(output).ok();
+        if let Some(contents) = &original_output {
+            std::fs::write(&staged, contents)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to write {}", staged.display()))?;
+        }
 
-            Write the resolved file to: /tmp/left.txt' --add-dir /tmp"#]].assert_eq(&displayed.to_string());
+        let staged_args = MergeArgs {
+            output: Some(staged.clone()),
+            output_permissions: None,
+            ..self.clone()
+        };
+        let outcome = staged_args.resolve_with_claude(config, color, progress, report)?;
+        if !matches!(outcome, Outcome::Resolved) {
+            return Ok(outcome);
+        }
+
+        if (self.backup || config.backup())
+            && let Some(contents) = &original_output
+        {
+            let backup = backup_output(output, contents)?;
+            tracing::info!("Backed up {} to {}", output.display(), backup.display());
+        }
+        let resolved = std::fs::read(&staged)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to read {}", staged.display()))?;
+        std::fs::write(output, resolved)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write {}", output.display()))?;
+        self.set_output_permissions()?;
+        Ok(Outcome::Resolved)
     }
 
-    #[test]
-    fn command_output_mode() {
-        let args = MergeArgs {
-            git_merge_driver: false,
-            base: PathBuf::from("/tmp/base.txt"),
-            left: PathBuf::from("/tmp/left.txt"),
-            right: PathBuf::from("/tmp/right.txt"),
-            output: Some(PathBuf::from("/tmp/output.txt")),
-            ancestor_label: Some("ancestor".to_string()),
-            left_label: "current".to_string(),
-            right_label: "incoming".to_string(),
-            filepath: Some("README.md".to_string()),
-            marker_size: Some(7),
+    /// Run Claude until it resolves the conflict, gives up, or fails.
+    fn resolve_with_claude(
+        &self,
+        config: &config::Config,
+        color: bool,
+        progress: &mut progress::ProgressReporter,
+        report: &mut webhook::MergeReport,
+    ) -> miette::Result<Outcome> {
+        let is_claude = *config.backend() == config::Backend::Claude;
+        let claude_version = if is_claude {
+            claude_version::check(config.claude_binary())?
+        } else {
+            None
         };
-        let command = args.command().unwrap();
-        let displayed: Utf8ProgramAndArgs = (&command).into();
-        expect![[r#"
-            claude --print --verbose '--output-format=stream-json' '--permission-mode=acceptEdits' --append-system-prompt 'You are resolving a merge conflict in `README.md`. Your working directory is the root of the repository, so you can browse and edit other files if needed (e.g. if code moved between files).
+        // If Claude is killed partway through writing the output, we put it back the way it was so
+        // that a half-written file isn't mistaken for a resolution.
+        let output_path = self.output_path()?;
+        let original_output = std::fs::read(output_path).ok();
+        // `--interactive` backs up the output when the resolution is accepted, instead of the
+        // staged copy Claude writes to.
+        if (self.backup || config.backup())
+            && !self.interactive
+            && let Some(contents) = &original_output
+        {
+            let backup = backup_output(output_path, contents)?;
+            tracing::info!(
+                "Backed up {} to {}",
+                output_path.display(),
+                backup.display()
+            );
+        }
+        let writer = claude_json::ClaudeEventWriter::new(claude_json::DisplayOptions {
+            plain: !color,
+            show_thinking: self.show_thinking || config.show_thinking(),
+            show_tool_results: self.show_tool_results || config.show_tool_results(),
+            quiet: self.quiet,
+            wrap_width: self.wrap_width,
+            max_cost_usd: self.max_cost_usd(config),
+            diff_lines: config.edit_diff_lines(),
+        })?
+        .with_temp_dirs(
+            config.canonicalize_tmpdir() && !self.no_canonicalize_tmpdir,
+            &[config.tmpdir_prefixes(), &self.tmpdir_prefix].concat(),
+        )
+        .with_pricing(config.pricing().clone());
+        let run_id = logging::new_run_id();
+        tracing::debug!("Run id: {run_id}");
+        let mut logger = if self.no_log || !config.log() {
+            logging::MergeLogger::disabled(&run_id)
+        } else {
+            logging::MergeLogger::new(
+                self.filepath.as_deref(),
+                &run_id,
+                config.log_retention_days(),
+            )
+        };
+        logger.set_claude_version(claude_version);
+        let timeout = self.timeout.or(config.timeout());
+        let idle_timeout = self.idle_timeout.or(config.idle_timeout());
+        let retry_on_markers = self.retry_on_markers || config.retry_on_markers();
+        let retry_prompt = self.retry_prompt.as_deref().or(config.retry_prompt());
+        let mut marker_retries = 0;
+        let mut clarifications = 0;
+        let mut terminal = Terminal::new(self.terminal.as_deref().unwrap_or(Path::new(TERMINAL)));
+        let retries = self.retries.unwrap_or(config.retries());
+        let mut failure_retries = 0;
+        let mut retry_note: Option<String> = None;
+        let mut spent_usd = None;
+        // Only the last run's result is printed for `--output-format json`, once the merge is over.
+        let mut last_result_json = None;
+        if let Some(filepath) = &self.filepath {
+            eprintln!(
+                "{}",
+                format!(
+                    "Resolving merge conflict in {}",
+                    filepath.if_supports_color(Stderr, |text| text.underline())
+                )
+                .if_supports_color(Stderr, |text| text.bold().green().to_string())
+            );
+        }
 
-            Three versions of the file are provided as temporary files: the base (common ancestor), left (current), and right (incoming). Read all three, understand what each side changed relative to the base, and write a resolved version to the output path. If changes are compatible, merge them cleanly. If they genuinely conflict, use your best judgment and explain your reasoning.' 'Resolve the merge conflict in `README.md`.
+        loop {
+            let mut child = self
+                .command_with_note(config, retry_note.as_deref())?
+                .spawn_checked()?;
+            if self.claude_stdin {
+                let mut stdin = child
+                    .child_mut()
+                    .stdin
+                    .take()
+                    .expect("claude piped stdin should have a stdin field");
+                let mut prompt = self.inline_prompt()?;
+                if let Some(note) = &retry_note {
+                    prompt.push_str("\n\n");
+                    prompt.push_str(note);
+                }
+                stdin.write_all(prompt.as_bytes()).into_diagnostic()?;
+                // Dropping `stdin` closes it so `claude` can start.
+            }
 
-            Read these three versions of the file:
-            - Base (common ancestor): /tmp/base.txt
-            - Left (current): /tmp/left.txt
-            - Right (incoming): /tmp/right.txt
+            let stdout = child
+                .child_mut()
+                .stdout
+                .take()
+                .expect("claude piped stdout should have a stdout field");
+            let (spinner, events_out): (_, Box<dyn Write>) = match self.output_format {
+                OutputFormat::Pretty => (progress::waiting_spinner(), Box::new(std::io::stderr())),
+                OutputFormat::Json => (ProgressBar::hidden(), Box::new(std::io::sink())),
+            };
+            let mut lines = watchdog::ChildLines::new(BufReader::new(stdout), timeout)
+                .with_idle_timeout(idle_timeout);
 
-            Write the resolved file to: /tmp/output.txt' --add-dir /tmp"#]].assert_eq(&displayed.to_string());
-    }
-}
+            let summary = read_events(
+                &mut lines,
+                &writer,
+                &mut logger,
+                progress,
+                &spinner,
+                events_out,
+                self.summary_line_to_stdout
+                    .then_some(&mut std::io::stdout() as &mut dyn Write),
+            )?;
+            let estimated_cost_usd = summary.estimated_cost_usd(config.pricing());
+            spent_usd = match (spent_usd, summary.total_cost_usd) {
+                (Some(spent), Some(cost)) => Some(spent + cost),
+                (spent, cost) => spent.or(cost),
+            };
+            report.total_cost_usd = spent_usd;
+            report
+                .edited_files
+                .extend(summary.edited_files.iter().cloned());
+            let EventSummary {
+                result_text,
+                saw_result,
+                total_cost_usd,
+                error_reason,
+                transient_error,
+                session_id,
+                web_search_requests,
+                result_json,
+                ..
+            } = summary;
 
-fn main() -> miette::Result<()> {
-    let cli = Cli::parse();
+            if let Some(timeout) = lines.timed_out() {
+                if let Err(err) = child.child_mut().kill() {
+                    tracing::warn!("Failed to kill claude: {err}");
+                }
+                let _ = child.child_mut().wait();
+                restore_output(output_path, original_output.as_deref());
+                let reason = match timeout {
+                    watchdog::Timeout::Total(timeout) => {
+                        format!("timed out after {}", humantime::format_duration(timeout))
+                    }
+                    watchdog::Timeout::Idle(idle) => format!(
+                        "produced no output for {}",
+                        humantime::format_duration(idle)
+                    ),
+                };
+                logger.log_abort(
+                    &format!("claude {reason}"),
+                    total_cost_usd,
+                    estimated_cost_usd,
+                );
+                return Err(match session_id {
+                    Some(session_id) => miette!(
+                        help = format!(
+                            "Run the merge again with `--resume {session_id}` to pick up where \
+                             Claude left off"
+                        ),
+                        "Claude {reason}"
+                    ),
+                    None => miette!("Claude {reason}"),
+                });
+            }
 
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::builder()
-                .with_default_directive(LevelFilter::INFO.into())
-                .from_env_lossy(),
+            // Wait before `wait_checked` so we can see whether `claude` was interrupted; the
+            // status is cached, so waiting again is fine. Ctrl-C reaches `claude` too, and our
+            // handler may not have run yet when it exits, so check both.
+            let claude_interrupted = child
+                .child_mut()
+                .wait()
+                .is_ok_and(|status| was_interrupted(&status));
+            if interrupt::interrupted() || claude_interrupted {
+                restore_output(output_path, original_output.as_deref());
+                logger.log_abort("interrupted", total_cost_usd, estimated_cost_usd);
+                return Err(miette!("Interrupted"));
+            }
+
+            let status = child.wait_checked();
+            let no_result = status.is_ok() && !saw_result && is_claude;
+            if let Err(err) = &status {
+                restore_output(output_path, original_output.as_deref());
+                logger.log_abort(
+                    &format!("claude failed: {err}"),
+                    total_cost_usd,
+                    estimated_cost_usd,
+                );
+            } else if let Some(reason) = &error_reason {
+                // `claude` can report an error, like running out of turns, and still exit 0.
+                restore_output(output_path, original_output.as_deref());
+                logger.log_abort(
+                    &format!("Claude failed: {reason}"),
+                    total_cost_usd,
+                    estimated_cost_usd,
+                );
+            } else if no_result {
+                restore_output(output_path, original_output.as_deref());
+                logger.log_abort(
+                    "claude exited without a result",
+                    total_cost_usd,
+                    estimated_cost_usd,
+                );
+            }
+
+            let failure = match (&status, &error_reason) {
+                (Err(err), _) => Some(format!("claude failed: {err}")),
+                (Ok(_), Some(reason)) => Some(format!("Claude failed: {reason}")),
+                (Ok(_), None) if no_result => Some("claude exited without a result".to_owned()),
+                (Ok(_), None) => None,
+            };
+            // A crash without a result could be a dropped connection, so it's worth another try,
+            // but an error `claude` reported is only retried if it might not happen again.
+            let retryable = !saw_result || transient_error;
+            if let Some(failure) = &failure
+                && !retryable
+            {
+                tracing::debug!("Not retrying: {failure}");
+            }
+            if let Some(failure) = failure
+                && retryable
+                && failure_retries < retries
+                && self
+                    .max_cost_usd(config)
+                    .is_none_or(|max_cost_usd| check_cost(spent_usd, max_cost_usd).is_ok())
+            {
+                failure_retries += 1;
+                restore_output(output_path, original_output.as_deref());
+                let delay = retry_delay(failure_retries);
+                tracing::warn!(
+                    "{failure}; retrying in {} (retry {failure_retries} of {retries})",
+                    humantime::format_duration(delay)
+                );
+                std::thread::sleep(delay);
+                continue;
+            }
+
+            status.map_err(unresolved_error)?;
+
+            if let Some(reason) = error_reason {
+                return Err(miette!("Claude failed to resolve the conflict: {reason}"));
+            }
+            if no_result {
+                return Err(miette!("Claude exited without a result"));
+            }
+
+            if result_json.is_some() {
+                last_result_json = result_json;
+            }
+
+            if let Some(max_cost_usd) = self.max_cost_usd(config)
+                && let Err(err) = check_cost(spent_usd, max_cost_usd)
+            {
+                logger.log_abort(&err.to_string(), total_cost_usd, estimated_cost_usd);
+                return Err(err);
+            }
+
+            if self.fail_on_web_search || config.fail_on_web_search() {
+                check_web_searches(web_search_requests)?;
+            }
+
+            if let Some(reason) = result_text.as_deref().and_then(markers::deferral_reason) {
+                if self.interactive
+                    && clarifications < MAX_CLARIFICATIONS
+                    && let Some(guidance) = self.ask_for_guidance(&mut terminal, reason)?
+                {
+                    clarifications += 1;
+                    retry_note = Some(format!(
+                        "You left this conflict for a human to resolve: {reason}\n\n\
+                         They replied: {guidance}\n\n\
+                         Resolve the conflict following their guidance."
+                    ));
+                    continue;
+                }
+                eprintln!(
+                    "{}",
+                    format!("Claude left the conflict for a human to resolve: {reason}")
+                        .if_supports_color(Stderr, |text| text.yellow().bold().to_string())
+                );
+                logger.log_deferred(reason, total_cost_usd);
+                self.print_result_json(last_result_json.as_deref());
+                return Ok(Outcome::Deferred);
+            }
+
+            if self.dry_run_claude {
+                tracing::info!("Dry run finished; the conflict is still unresolved");
+                self.print_result_json(last_result_json.as_deref());
+                return Ok(Outcome::DryRun);
+            }
+
+            if self.claude_stdin {
+                let result = result_text
+                    .as_deref()
+                    .ok_or_else(|| miette!("Claude finished without a result"))?;
+                write_inline_resolution(result, self.output_path()?)?;
+            }
+
+            let markers = markers::check_file(
+                self.output_path()?,
+                self.marker_size.unwrap_or(markers::DEFAULT_MARKER_SIZE),
+            );
+            match markers {
+                Err(err) if retry_on_markers && marker_retries < MAX_MARKER_RETRIES => {
+                    marker_retries += 1;
+                    tracing::warn!(
+                        "{err}; asking Claude to finish resolving it (retry {marker_retries} of \
+                         {MAX_MARKER_RETRIES})"
+                    );
+                    retry_note = Some(RetryReason::Markers(err.to_string()).prompt(retry_prompt));
+                    continue;
+                }
+                Err(err) if self.allow_markers => {
+                    tracing::warn!("{err}");
+                }
+                markers => markers?,
+            }
+
+            if self.apply_only_if_valid_json
+                && retry_on_markers
+                && marker_retries < MAX_MARKER_RETRIES
+            {
+                let output = self.output_path()?;
+                let filepath = self.filepath.as_deref().map_or(output, Path::new);
+                if let Some((format, err)) =
+                    validate::Validators::with_overrides(&self.validate_extension)
+                        .check_file(filepath, output)?
+                {
+                    marker_retries += 1;
+                    let reason = format!("the resolved file isn't valid {format}: {err}");
+                    tracing::warn!(
+                        "{reason}; asking Claude to fix it (retry {marker_retries} of \
+                         {MAX_MARKER_RETRIES})"
+                    );
+                    retry_note = Some(RetryReason::Validation(reason).prompt(retry_prompt));
+                    continue;
+                }
+            }
+
+            if self.attribution && self.output_format == OutputFormat::Pretty {
+                match result_text.as_deref().and_then(attribution::parse) {
+                    Some(hunks) => eprintln!("{}", attribution::Summary(&hunks)),
+                    None => tracing::warn!("Claude didn't say which side it took for each hunk"),
+                }
+            }
+            break;
+        }
+
+        if self.apply_only_if_valid_json {
+            let output = self.output_path()?;
+            let filepath = self.filepath.as_deref().map_or(output, Path::new);
+            validate::Validators::with_overrides(&self.validate_extension)
+                .validate_file(filepath, output)?;
+        }
+
+        if self.show_diff && !self.interactive {
+            eprint!(
+                "{}",
+                self.resolution_diff(output_path, original_output.as_deref())?
+            );
+        }
+
+        if self.interactive
+            && !self.confirm_resolution(&mut terminal, output_path, original_output.as_deref())?
+        {
+            restore_output(output_path, original_output.as_deref());
+            eprintln!("Discarded Claude's resolution; the conflict is still unresolved");
+            return Ok(Outcome::Rejected);
+        }
+
+        self.set_output_permissions()?;
+        self.print_result_json(last_result_json.as_deref());
+        Ok(Outcome::Resolved)
+    }
+
+    /// Print Claude's result for `--output-format json`.
+    fn print_result_json(&self, json: Option<&str>) {
+        if self.output_format == OutputFormat::Json
+            && let Some(json) = json
+        {
+            println!("{json}");
+        }
+    }
+
+    /// A diff of the resolved file against the left side, before Claude touched it.
+    fn resolution_diff(
+        &self,
+        output: &Path,
+        original_output: Option<&[u8]>,
+    ) -> miette::Result<String> {
+        let read = |path: &Path| {
+            std::fs::read(path)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to read {}", path.display()))
+        };
+        // As a merge driver, Claude overwrote the left side, so use what it was beforehand.
+        let left = match original_output {
+            Some(original) if output == self.left => original.to_vec(),
+            _ => read(&self.left)?,
+        };
+        let resolved = read(output)?;
+        Ok(diff::unified_diff(
+            &String::from_utf8_lossy(&left),
+            &String::from_utf8_lossy(&resolved),
+            &format!("{} (left)", self.filepath()),
+            &format!("{} (resolved)", self.filepath()),
+        ))
+    }
+
+    /// Show Claude's resolution as a diff against the left side and ask whether to keep it.
+    ///
+    /// Without a terminal to ask on, like in CI, the resolution is kept.
+    fn confirm_resolution(
+        &self,
+        terminal: &mut Terminal,
+        output: &Path,
+        original_output: Option<&[u8]>,
+    ) -> miette::Result<bool> {
+        let Some(terminal) = terminal.input("asking for confirmation") else {
+            return Ok(true);
+        };
+        eprint!("{}", self.resolution_diff(output, original_output)?);
+        read_confirmation("Accept this resolution?", terminal, std::io::stderr())
+    }
+
+    /// Ask on the terminal how to resolve a conflict Claude left for a human, returning `None`
+    /// if there's no answer.
+    fn ask_for_guidance(
+        &self,
+        terminal: &mut Terminal,
+        reason: &str,
+    ) -> miette::Result<Option<String>> {
+        let Some(terminal) = terminal.input("asking for guidance") else {
+            return Ok(None);
+        };
+        eprintln!("Claude needs a decision: {reason}");
+        read_answer(
+            "How should Claude resolve the conflict? (leave blank to resolve it yourself)",
+            terminal,
+            std::io::stderr(),
+        )
+    }
+
+    /// Handle a file matching a `[[regenerate]]` rule without Claude, if there's a rule for it.
+    fn regenerate(&self, config: &config::Config) -> miette::Result<Option<Outcome>> {
+        let output = self.output_path()?;
+        let filepath = self.filepath.as_deref().map_or(output, Path::new);
+        let Some(rule) = config
+            .regenerate()
+            .iter()
+            .find(|rule| glob_matches(&rule.glob, filepath))
+        else {
+            return Ok(None);
+        };
+
+        let Some((program, args)) = rule.command.split_first() else {
+            eprintln!(
+                "{}",
+                format!(
+                    "{} matches the regenerate glob `{}`; take one side and regenerate it instead \
+                     of merging it",
+                    filepath.display(),
+                    rule.glob,
+                )
+                .if_supports_color(Stderr, |text| text.yellow().bold().to_string())
+            );
+            return Ok(Some(Outcome::Deferred));
+        };
+
+        let command = lockfile::command_in_dir(program, args, filepath);
+        if self.dry_run || self.dry_run_claude {
+            println!("{}", Utf8ProgramAndArgs::from(&command));
+            return Ok(Some(if self.dry_run_claude {
+                Outcome::DryRun
+            } else {
+                Outcome::Resolved
+            }));
+        }
+        tracing::info!(
+            "Regenerating {} without Claude (matches `{}`)",
+            filepath.display(),
+            rule.glob
+        );
+        lockfile::regenerate(command, &self.left, filepath, output)?;
+        self.set_output_permissions()?;
+        Ok(Some(Outcome::Resolved))
+    }
+
+    /// As a Git merge driver, we're run for every file changed on both sides, not just the ones
+    /// that conflict, so try Git's own merge first and only ask Claude if that leaves conflicts.
+    fn merge_without_claude(&self) -> miette::Result<Option<Outcome>> {
+        if !self.git_merge_driver || self.dry_run_claude || !self.extra_sides.is_empty() {
+            return Ok(None);
+        }
+        match git::merge_file(self.base()?, &self.left, &self.right)? {
+            git::MergeFile::Clean(merged) => {
+                let output = self.output_path()?;
+                std::fs::write(output, merged)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("Failed to write {}", output.display()))?;
+                self.set_output_permissions()?;
+                tracing::info!("Git merged {} without conflicts", self.filepath());
+                Ok(Some(Outcome::Resolved))
+            }
+            git::MergeFile::Conflicted(_) => Ok(None),
+        }
+    }
+
+    /// Apply `--output-permissions`, if given.
+    fn set_output_permissions(&self) -> miette::Result<()> {
+        match self.output_permissions {
+            Some(mode) => set_permissions(self.output_path()?, mode),
+            None => Ok(()),
+        }
+    }
+}
+
+/// How a merge finished, if it didn't fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Resolved,
+    /// Claude deliberately left the conflict for a human.
+    Deferred,
+    /// Claude only planned the resolution, for `--dry-run-claude`.
+    DryRun,
+    /// The user discarded Claude's resolution, for `--interactive`.
+    Rejected,
+}
+
+impl Outcome {
+    fn exit_code(self) -> ExitCode {
+        match self {
+            Outcome::Resolved => ExitCode::SUCCESS,
+            // Distinct from the `1` used for errors.
+            Outcome::Deferred => ExitCode::from(2),
+            // So that Git and jj don't take the untouched file as resolved.
+            Outcome::DryRun | Outcome::Rejected => ExitCode::FAILURE,
+        }
+    }
+}
+
+/// How many times `--retry-on-markers` runs Claude again, on top of the first run.
+const MAX_MARKER_RETRIES: u32 = 1;
+
+/// Why `--retry-on-markers` is running Claude again, with what went wrong.
+#[derive(Debug)]
+enum RetryReason {
+    Markers(String),
+    Validation(String),
+}
+
+impl RetryReason {
+    /// The note added to the user prompt, from the `retry_prompt` template if there is one.
+    fn prompt(&self, template: Option<&str>) -> String {
+        let (kind, reason) = match self {
+            RetryReason::Markers(reason) => ("markers", reason),
+            RetryReason::Validation(reason) => ("validation", reason),
+        };
+        match (template, self) {
+            (Some(template), _) => {
+                substitute_placeholders(template, &[("kind", kind), ("reason", reason)])
+            }
+            (None, RetryReason::Markers(_)) => format!(
+                "You already tried to resolve this conflict, but {reason}. Resolve the remaining \
+                 conflicts, keeping the parts you've already resolved."
+            ),
+            (None, RetryReason::Validation(_)) => format!(
+                "You already tried to resolve this conflict, but {reason}. Fix the resolved \
+                 file, keeping the parts you've already resolved."
+            ),
+        }
+    }
+}
+
+/// How many times `--interactive` asks for guidance on a conflict Claude left for a human.
+const MAX_CLARIFICATIONS: u32 = 3;
+
+/// Whether `claude` was stopped by Ctrl-C, in which case the user doesn't want it run again.
+fn was_interrupted(status: &std::process::ExitStatus) -> bool {
+    const SIGINT: i32 = 2;
+    #[cfg(unix)]
+    if std::os::unix::process::ExitStatusExt::signal(status) == Some(SIGINT) {
+        return true;
+    }
+    // Wrapper scripts report a child killed by a signal as exiting with 128 plus the signal.
+    status.code() == Some(128 + SIGINT)
+}
+
+/// How long to wait before the `retry`th retry after Claude fails, starting at 1. The delay
+/// doubles each time, up to a minute.
+fn retry_delay(retry: u32) -> Duration {
+    const FIRST: Duration = Duration::from_secs(1);
+    const MAX: Duration = Duration::from_secs(60);
+    FIRST
+        .checked_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+        .map_or(MAX, |delay| delay.min(MAX))
+}
+
+/// What we learned from `claude`'s event stream.
+#[derive(Debug, Default)]
+struct EventSummary {
+    /// The final reply, if `claude` reported a successful result.
+    result_text: Option<String>,
+    /// Whether `claude` reported a result at all.
+    saw_result: bool,
+    total_cost_usd: Option<f64>,
+    /// Why `claude` failed, if it reported an error result.
+    error_reason: Option<String>,
+    /// Whether `claude` reported an error that might go away if we run it again.
+    transient_error: bool,
+    /// The ID of Claude's session, for `--resume`.
+    session_id: Option<String>,
+    web_search_requests: u64,
+    /// Files Claude wrote or edited.
+    edited_files: BTreeSet<String>,
+    /// The successful result event as JSON, for `--output-format json`.
+    result_json: Option<String>,
+    /// Tokens used by each of Claude's messages, by message ID.
+    message_usage: BTreeMap<String, claude_json::MessageUsage>,
+}
+
+impl EventSummary {
+    /// Estimate the cost so far from the tokens used, for when `claude` is killed before it
+    /// reports a cost. `None` if we don't know the rates for one of the models used.
+    fn estimated_cost_usd(&self, pricing: &BTreeMap<String, pricing::ModelPricing>) -> Option<f64> {
+        if self.message_usage.is_empty() {
+            return None;
+        }
+        self.message_usage
+            .values()
+            .map(|message| {
+                pricing::ModelPricing::lookup(pricing, &message.model)
+                    .map(|rates| rates.cost_usd(&message.usage))
+            })
+            .sum()
+    }
+}
+
+/// Display and log each line of `claude`'s `stream-json` output.
+///
+/// The result is written to `result_out` if it's given, and to `out` with everything else
+/// otherwise.
+fn read_events(
+    lines: impl Iterator<Item = std::io::Result<String>>,
+    writer: &claude_json::ClaudeEventWriter,
+    logger: &mut logging::MergeLogger,
+    progress: &mut progress::ProgressReporter,
+    spinner: &ProgressBar,
+    mut out: impl Write,
+    mut result_out: Option<&mut dyn Write>,
+) -> miette::Result<EventSummary> {
+    let mut summary = EventSummary::default();
+    let skipped_before = writer.skipped_events();
+
+    for line in lines {
+        match line {
+            Ok(line) => {
+                logger.log_event(&line);
+                if !line.starts_with('{') {
+                    // Not a Claude event, probably plain output from a custom backend.
+                    spinner.finish_and_clear();
+                    writeln!(out, "{line}").into_diagnostic()?;
+                } else if let Some(event) = writer.display(&line) {
+                    progress.observe(&event);
+                    summary
+                        .edited_files
+                        .extend(event.written_files().map(str::to_owned));
+                    if event.is_result() {
+                        summary.saw_result = true;
+                        logger.log_summary(&line);
+                    }
+                    if let Some(cost) = event.total_cost_usd() {
+                        summary.total_cost_usd = Some(cost);
+                    }
+                    if let Some(reason) = event.error_reason() {
+                        summary.error_reason = Some(reason);
+                    }
+                    summary.transient_error |= event.is_transient_error();
+                    if let Some(session_id) = event.session_id() {
+                        logger.set_session_id(session_id);
+                        summary.session_id = Some(session_id.to_owned());
+                    }
+                    summary.web_search_requests += event.web_search_requests();
+                    if let Some(usage) = event.message_usage() {
+                        summary.message_usage.insert(usage.id.clone(), usage);
+                    }
+                    if let Some(text) = event.result_text() {
+                        summary.result_text = Some(text.to_owned());
+                    }
+                    if let Some(json) = event.result_json() {
+                        summary.result_json = Some(json);
+                    }
+                    let text = event.to_string();
+                    if !text.is_empty() {
+                        spinner.finish_and_clear();
+                        match &mut result_out {
+                            Some(result_out) if event.is_result() => {
+                                write!(result_out, "{text}").into_diagnostic()?
+                            }
+                            _ => write!(out, "{text}").into_diagnostic()?,
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::debug!("{err}");
+            }
+        }
+    }
+
+    spinner.finish_and_clear();
+    let skipped = writer.skipped_events() - skipped_before;
+    if skipped > 0 {
+        tracing::debug!("Skipped {skipped} of Claude's events that we couldn't parse");
+    }
+    Ok(summary)
+}
+
+/// Whether any line in `contents` is longer than `max` characters.
+fn has_huge_line(contents: &str, max: usize) -> bool {
+    contents
+        .lines()
+        // A line can't have more characters than bytes, so only count the long ones.
+        .any(|line| line.len() > max && line.chars().count() > max)
+}
+
+/// Whether `base` is a stand-in for a missing common ancestor, as in add/add conflicts.
+fn is_missing_base(base: &Path) -> bool {
+    base == Path::new("/dev/null") || std::fs::metadata(base).is_ok_and(|meta| meta.len() == 0)
+}
+
+/// Replace `{name}` in `template` with the corresponding value. Unknown placeholders are left
+/// alone, and substituted values aren't searched for more placeholders.
+fn substitute_placeholders(template: &str, values: &[(&str, &str)]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let placeholder = values.iter().find(|(name, _)| {
+            rest.strip_prefix(name)
+                .is_some_and(|after| after.starts_with('}'))
+        });
+        match placeholder {
+            Some((name, value)) => {
+                result.push_str(value);
+                rest = &rest[name.len() + 1..];
+            }
+            None => result.push('{'),
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Write the output file's original `contents` to `<path>.orig`, or to a timestamped
+/// `<path>.<time>.orig` if that already exists, returning the backup's path.
+fn backup_output(path: &Path, contents: &[u8]) -> miette::Result<PathBuf> {
+    let with_suffix = |suffix: &str| {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(suffix);
+        PathBuf::from(backup)
+    };
+    let mut backup = with_suffix(".orig");
+    if backup.exists() {
+        backup = with_suffix(&format!(".{}.orig", logging::format_timestamp()));
+    }
+    std::fs::write(&backup, contents)
+        .into_diagnostic()
+        .wrap_err_with(|| {
+            format!(
+                "Failed to back up {} to {}",
+                path.display(),
+                backup.display()
+            )
+        })?;
+    Ok(backup)
+}
+
+/// Put the output file back the way it was before Claude ran, deleting it if it didn't exist.
+fn restore_output(path: &Path, original: Option<&[u8]>) {
+    let result = match original {
+        Some(contents) => std::fs::write(path, contents),
+        None => match std::fs::remove_file(path) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        },
+    };
+    if let Err(err) = result {
+        tracing::warn!("Failed to restore {}: {err}", path.display());
+    }
+}
+
+/// The controlling terminal, which we can ask questions on even when `git mergetool` has closed
+/// our stdin.
+#[cfg(unix)]
+const TERMINAL: &str = "/dev/tty";
+#[cfg(windows)]
+const TERMINAL: &str = "CONIN$";
+
+/// The terminal `--interactive` asks questions on, opened when it's first needed so that merges
+/// which don't ask anything work without one.
+struct Terminal<'a> {
+    path: &'a Path,
+    input: Option<BufReader<std::fs::File>>,
+}
+
+impl<'a> Terminal<'a> {
+    fn new(path: &'a Path) -> Self {
+        Self { path, input: None }
+    }
+
+    /// The terminal's input, or `None` (with a warning) if it can't be opened.
+    fn input(&mut self, purpose: &str) -> Option<&mut BufReader<std::fs::File>> {
+        if self.input.is_none() {
+            match std::fs::File::open(self.path) {
+                Ok(file) => self.input = Some(BufReader::new(file)),
+                Err(err) => {
+                    tracing::warn!(
+                        "Not {purpose} because {} isn't usable: {err}",
+                        self.path.display()
+                    );
+                    return None;
+                }
+            }
+        }
+        self.input.as_mut()
+    }
+}
+
+/// Ask a question, returning `None` for a blank answer.
+fn read_answer(
+    question: &str,
+    mut input: impl BufRead,
+    mut out: impl Write,
+) -> miette::Result<Option<String>> {
+    writeln!(out, "{question}")
+        .and_then(|()| write!(out, "> "))
+        .and_then(|()| out.flush())
+        .into_diagnostic()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer).into_diagnostic()?;
+    let answer = answer.trim();
+    Ok((!answer.is_empty()).then(|| answer.to_owned()))
+}
+
+/// Ask a yes-or-no question, defaulting to no.
+fn read_confirmation(
+    question: &str,
+    mut input: impl BufRead,
+    mut out: impl Write,
+) -> miette::Result<bool> {
+    write!(out, "{question} [y/N] ")
+        .and_then(|()| out.flush())
+        .into_diagnostic()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer).into_diagnostic()?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+#[cfg(unix)]
+fn set_permissions(path: &Path, mode: u32) -> miette::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to set permissions of {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn set_permissions(_path: &Path, _mode: u32) -> miette::Result<()> {
+    Err(miette!("--output-permissions is only supported on Unix"))
+}
+
+/// Explain what a failed agent run means for the merge.
+///
+/// The raw exit status is reported, but we always exit with 1: Git (with `trustExitCode`) and
+/// jj only care that the merge tool failed.
+fn unresolved_error(err: impl std::fmt::Display) -> miette::Report {
+    miette!(
+        help = "Git (with `trustExitCode`) and jj keep the file conflicted, with the conflict \
+                markers left in place; resolve it by hand or run the merge again",
+        "The merge was not resolved: {err}"
+    )
+}
+
+/// Enforce `--max-cost`.
+fn check_cost(total_cost_usd: Option<f64>, max_cost_usd: f64) -> miette::Result<()> {
+    if let Some(cost) = total_cost_usd
+        && cost > max_cost_usd
+    {
+        return Err(miette!(
+            "Claude spent {}, over the {} budget",
+            claude_json::Dollars(cost),
+            claude_json::Dollars(max_cost_usd)
+        ));
+    }
+    Ok(())
+}
+
+/// Enforce `--fail-on-web-search`.
+fn check_web_searches(web_search_requests: u64) -> miette::Result<()> {
+    if web_search_requests > 0 {
+        return Err(miette!(
+            "Claude made {web_search_requests} web search request(s), which --fail-on-web-search \
+            forbids"
+        ));
+    }
+    Ok(())
+}
+
+/// Extract the file between `<resolved>` tags in Claude's reply for `--claude-stdin`.
+fn extract_inline_resolution(reply: &str) -> Option<&str> {
+    let start = reply.find("<resolved>")? + "<resolved>".len();
+    let end = reply.rfind("</resolved>")?;
+    let resolved = reply.get(start..end)?;
+    Some(resolved.strip_prefix('\n').unwrap_or(resolved))
+}
+
+fn write_inline_resolution(reply: &str, output: &Path) -> miette::Result<()> {
+    let resolved = extract_inline_resolution(reply)
+        .ok_or_else(|| miette!("Claude's reply didn't contain a `<resolved>` file"))?;
+    std::fs::write(output, resolved)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to write {}", output.display()))
+}
+
+/// Conflict labels, after applying `--labels-file` and defaults.
+#[derive(Debug, PartialEq, Eq)]
+struct Labels {
+    ancestor: Option<String>,
+    left: String,
+    right: String,
+}
+
+impl Labels {
+    /// The ancestor label, if the VCS gave one, for describing the base version.
+    fn base(&self) -> &str {
+        self.ancestor.as_deref().unwrap_or("common ancestor")
+    }
+}
+
+/// Parse a labels file: the ancestor, left, and right labels, one per line. Blank or missing lines
+/// leave the corresponding label unset.
+fn parse_labels_file(contents: &str) -> [Option<String>; 3] {
+    let mut lines = contents.lines().map(|line| {
+        let line = line.trim();
+        (!line.is_empty()).then(|| line.to_owned())
+    });
+    std::array::from_fn(|_| lines.next().flatten())
+}
+
+/// Parse a `KEY=VALUE` pair for `--claude-env`.
+fn parse_env_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(format!("expected `KEY=VALUE`, got `{s}`")),
+    }
+}
+
+/// Whether `path` matches `pattern`: `*` and `?` match within a path component and `**` matches
+/// across components. Patterns without a `/` only match the file name.
+fn glob_matches(pattern: &str, path: &Path) -> bool {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let path = path.strip_prefix("./").unwrap_or(&path);
+    let text = if pattern.contains('/') {
+        path
+    } else {
+        path.rsplit('/').next().unwrap_or(path)
+    };
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    glob_match(&pattern, &text)
+}
+
+fn glob_match(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        // `**/` matches zero or more whole directories.
+        ['*', '*', '/', rest @ ..] => {
+            glob_match(rest, text)
+                || (0..text.len()).any(|i| text[i] == '/' && glob_match(rest, &text[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| glob_match(rest, &text[i..])),
+        ['?', rest @ ..] => {
+            matches!(text, [c, tail @ ..] if *c != '/' && glob_match(rest, tail))
+        }
+        [c, rest @ ..] => matches!(text, [t, tail @ ..] if t == c && glob_match(rest, tail)),
+    }
+}
+
+/// `args` without any `--model <MODEL>` or `--model=<MODEL>` arguments.
+fn without_model_args(args: &[String]) -> Vec<&String> {
+    let mut kept = Vec::with_capacity(args.len());
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--model" {
+            args.next();
+        } else if !arg.starts_with("--model=") {
+            kept.push(arg);
+        }
+    }
+    kept
+}
+
+fn parse_mode(s: &str) -> Result<u32, String> {
+    let digits = s.strip_prefix("0o").unwrap_or(s);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!(
+            "expected an octal file mode like `0644`, got `{s}`"
+        )),
+    }
+}
+
+/// Drop any directory that's already covered by one of its ancestors in `dirs`.
+fn dedupe_dirs(dirs: BTreeSet<&Path>) -> BTreeSet<&Path> {
+    let mut deduped = BTreeSet::new();
+    // `Path`s are ordered component-wise, so ancestors always come before their descendants.
+    for dir in dirs {
+        if !deduped.iter().any(|ancestor| dir.starts_with(ancestor)) {
+            deduped.insert(dir);
+        }
+    }
+    deduped
+}
+
+fn main() -> miette::Result<ExitCode> {
+    let cli = Cli::parse();
+    let color = cli.color().enabled();
+    owo_colors::set_override(color);
+    miette::set_hook(Box::new(move |_| {
+        Box::new(miette::MietteHandlerOpts::new().color(color).build())
+    }))?;
+
+    logging::install_panic_hook();
+
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
         )
         .without_time()
+        .with_ansi(color)
         .with_writer(std::io::stderr)
         .init();
 
     tracing::debug!("Parsed arguments:{cli:#?}");
 
     match cli.command {
-        Commands::Merge(args) => args.run()?,
+        Commands::Merge(args) => return args.run(color),
         Commands::Install(install) => install.run()?,
+        Commands::Uninstall(uninstall) => uninstall.run()?,
+        Commands::GenerateConfig(args) => args.run()?,
+        Commands::ConfigSchema => println!("{}", config::schema()),
+        Commands::Stats(args) => args.run()?,
+        Commands::Eval(args) => args.run(color)?,
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command_error::Utf8ProgramAndArgs;
+    use expect_test::expect;
+    use std::collections::BTreeMap;
+
+    /// Parse `claude-mergetool merge <args>`.
+    fn merge_args(args: &[&str]) -> MergeArgs {
+        let cli = Cli::try_parse_from(["claude-mergetool", "merge"].iter().chain(args)).unwrap();
+        match cli.command {
+            Commands::Merge(args) => *args,
+            command => panic!("expected a merge command, got {command:?}"),
+        }
+    }
+
+    impl MergeArgs {
+        /// [`MergeArgs::resolve`] with the `--config` file, like [`MergeArgs::run`] does.
+        fn resolve_with_config_file(
+            &self,
+            report: &mut webhook::MergeReport,
+        ) -> miette::Result<Outcome> {
+            let config = config::load_config(self.config.as_deref())?;
+            self.resolve(
+                &config,
+                false,
+                &mut progress::ProgressReporter::disabled(),
+                report,
+            )
+        }
+    }
+
+    #[test]
+    fn command_git_mode() {
+        let args = merge_args(&[
+            "--git-merge-driver",
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-p",
+            "src/lib.rs",
+        ]);
+        let command = args.command(&config::Config::default()).unwrap();
+        let displayed: Utf8ProgramAndArgs = (&command).into();
+        expect![[r#"
+            claude --print --verbose '--output-format=stream-json' '--permission-mode=acceptEdits' --append-system-prompt 'You are resolving a merge conflict in `src/lib.rs`. Your working directory is the root of the repository, so you can browse and edit other files if needed (e.g. if code moved between files).
+
+            Three versions of the file are provided as temporary files: the base (common ancestor), left (ours), and right (theirs). Read all three, understand what each side changed relative to the base, and write a resolved version to the output path. If changes are compatible, merge them cleanly. If they genuinely conflict, use your best judgment and explain your reasoning. If the conflict is so ambiguous that only a human can decide, leave the conflict markers in place and end your reply with a line starting with `UNRESOLVED:` explaining why.' 'Resolve the merge conflict in `src/lib.rs`.
+
+            Read these three versions of the file:
+            - Base (common ancestor): /tmp/base.txt
+            - Left (ours): /tmp/left.txt
+            - Right (theirs): /tmp/right.txt
+
+            Write the resolved file to: /tmp/left.txt' --add-dir /tmp"#]].assert_eq(&displayed.to_string());
+    }
+
+    #[test]
+    fn command_output_mode() {
+        let args = merge_args(&[
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+            "-s",
+            "ancestor",
+            "-x",
+            "current",
+            "-y",
+            "incoming",
+            "-p",
+            "README.md",
+            "-l",
+            "7",
+        ]);
+        let command = args.command(&config::Config::default()).unwrap();
+        let displayed: Utf8ProgramAndArgs = (&command).into();
+        expect![[r#"
+            claude --print --verbose '--output-format=stream-json' '--permission-mode=acceptEdits' --append-system-prompt 'You are resolving a merge conflict in `README.md`. Your working directory is the root of the repository, so you can browse and edit other files if needed (e.g. if code moved between files).
+
+            Three versions of the file are provided as temporary files: the base (ancestor), left (current), and right (incoming). Read all three, understand what each side changed relative to the base, and write a resolved version to the output path. If changes are compatible, merge them cleanly. If they genuinely conflict, use your best judgment and explain your reasoning. If the conflict is so ambiguous that only a human can decide, leave the conflict markers in place and end your reply with a line starting with `UNRESOLVED:` explaining why.' 'Resolve the merge conflict in `README.md`.
+
+            Read these three versions of the file:
+            - Base (ancestor): /tmp/base.txt
+            - Left (current): /tmp/left.txt
+            - Right (incoming): /tmp/right.txt
+
+            Write the resolved file to: /tmp/output.txt
+
+            Conflict markers in these files are 7 characters wide (e.g. `<<<<<<<`).' --add-dir /tmp"#]].assert_eq(&displayed.to_string());
+    }
+
+    #[test]
+    fn command_with_config() {
+        let config: config::Config = toml::from_str(
+            r#"
+            claude_binary = "claude-code"
+            permission_mode = "plan"
+            model = "sonnet"
+            extra_args = ["--max-turns", "20"]
+            append_system_prompt = "Keep imports sorted."
+            "#,
+        )
+        .unwrap();
+        let args = merge_args(&[
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+            "-p",
+            "src/main.rs",
+        ]);
+        let command = args.command(&config).unwrap();
+        let displayed: Utf8ProgramAndArgs = (&command).into();
+        expect![[r#"
+            claude-code --print --verbose '--output-format=stream-json' '--permission-mode=plan' --append-system-prompt 'You are resolving a merge conflict in `src/main.rs`. Your working directory is the root of the repository, so you can browse and edit other files if needed (e.g. if code moved between files).
+
+            Three versions of the file are provided as temporary files: the base (common ancestor), left (ours), and right (theirs). Read all three, understand what each side changed relative to the base, and write a resolved version to the output path. If changes are compatible, merge them cleanly. If they genuinely conflict, use your best judgment and explain your reasoning. If the conflict is so ambiguous that only a human can decide, leave the conflict markers in place and end your reply with a line starting with `UNRESOLVED:` explaining why.
+
+            Keep imports sorted.' --model sonnet 'Resolve the merge conflict in `src/main.rs`.
+
+            Read these three versions of the file:
+            - Base (common ancestor): /tmp/base.txt
+            - Left (ours): /tmp/left.txt
+            - Right (theirs): /tmp/right.txt
+
+            Write the resolved file to: /tmp/output.txt' --add-dir /tmp --max-turns 20"#]].assert_eq(&displayed.to_string());
+    }
+
+    #[test]
+    fn parse_config_flag() {
+        let args = merge_args(&[
+            "--config",
+            "./project.toml",
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+        ]);
+        assert_eq!(args.config.as_deref(), Some(Path::new("./project.toml")));
+    }
+
+    #[test]
+    fn command_claude_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, contents: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            path.to_str().unwrap().to_owned()
+        };
+        let base = write("base", "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n");
+        let left = write("left", "fn add(a: i64, b: i64) -> i64 {\n    a + b\n}\n");
+        let right = write("right", "fn add(a: i32, b: i32) -> i32 {\n    b + a\n}");
+        let output = dir.path().join("output");
+
+        let args = merge_args(&[
+            "--claude-stdin",
+            &base,
+            &left,
+            &right,
+            "-o",
+            output.to_str().unwrap(),
+            "-p",
+            "src/lib.rs",
+        ]);
+
+        let command = args.command(&config::Config::default()).unwrap();
+        assert!(
+            !command.get_args().any(|arg| arg == "--add-dir"),
+            "temp dirs shouldn't be granted with --claude-stdin"
+        );
+
+        expect![[r#"
+            Resolve the merge conflict in `src/lib.rs`.
+
+            Base (common ancestor):
+            <base>
+            fn add(a: i32, b: i32) -> i32 {
+                a + b
+            }
+            </base>
+
+            Left (ours):
+            <left>
+            fn add(a: i64, b: i64) -> i64 {
+                a + b
+            }
+            </left>
+
+            Right (theirs):
+            <right>
+            fn add(a: i32, b: i32) -> i32 {
+                b + a
+            }
+            </right>
+
+            Reply with the complete resolved file between `<resolved>` and `</resolved>` tags."#]].assert_eq(&args.inline_prompt().unwrap());
+
+        write_inline_resolution(
+            "Both sides changed `add`, so I kept the wider types.\n\n\
+             <resolved>\nfn add(a: i64, b: i64) -> i64 {\n    b + a\n}\n</resolved>\n",
+            &output,
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "fn add(a: i64, b: i64) -> i64 {\n    b + a\n}\n"
+        );
+    }
+
+    #[test]
+    fn extract_inline_resolution_missing() {
+        assert_eq!(extract_inline_resolution("I couldn't resolve it."), None);
+        assert_eq!(extract_inline_resolution("<resolved>\n"), None);
+    }
+
+    #[test]
+    fn command_claude_env() {
+        let config: config::Config = toml::from_str(
+            r#"
+            [claude_env]
+            ANTHROPIC_BASE_URL = "https://llm-gateway.example.com"
+            CLAUDE_CODE_USE_BEDROCK = "1"
+            "#,
+        )
+        .unwrap();
+        let args = merge_args(&[
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+            "--claude-env",
+            "CLAUDE_CODE_USE_BEDROCK=0",
+            "--claude-env",
+            "HTTPS_PROXY=http://proxy:3128",
+        ]);
+        let command = args.command(&config).unwrap();
+        let envs: BTreeMap<_, _> = command
+            .get_envs()
+            .map(|(key, value)| (key.to_str().unwrap(), value.and_then(|v| v.to_str())))
+            .collect();
+        assert_eq!(
+            envs,
+            BTreeMap::from([
+                (
+                    "ANTHROPIC_BASE_URL",
+                    Some("https://llm-gateway.example.com")
+                ),
+                ("CLAUDE_CODE_USE_BEDROCK", Some("0")),
+                ("HTTPS_PROXY", Some("http://proxy:3128")),
+            ])
+        );
+    }
+
+    #[test]
+    fn parse_env_var_syntax() {
+        assert_eq!(
+            parse_env_var("KEY=a=b"),
+            Ok(("KEY".to_owned(), "a=b".to_owned()))
+        );
+        assert_eq!(parse_env_var("KEY="), Ok(("KEY".to_owned(), "".to_owned())));
+        assert!(parse_env_var("KEY").is_err());
+        assert!(parse_env_var("=VALUE").is_err());
+    }
+
+    #[test]
+    fn dedupe_dirs_nested() {
+        let dirs = BTreeSet::from([Path::new("/a"), Path::new("/a/b")]);
+        assert_eq!(dedupe_dirs(dirs), BTreeSet::from([Path::new("/a")]));
+    }
+
+    #[test]
+    fn dedupe_dirs_siblings() {
+        let dirs = BTreeSet::from([
+            Path::new("/a/b"),
+            Path::new("/a/c/d"),
+            Path::new("/a/c"),
+            Path::new("/ab"),
+        ]);
+        assert_eq!(
+            dedupe_dirs(dirs),
+            BTreeSet::from([Path::new("/a/b"), Path::new("/a/c"), Path::new("/ab")])
+        );
+    }
+
+    #[test]
+    fn command_dedupe_add_dir() {
+        let args = merge_args(&[
+            "--dedupe-add-dir",
+            "/a/base.txt",
+            "/a/b/left.txt",
+            "/a/b/c/right.txt",
+            "-o",
+            "/a/output.txt",
+        ]);
+        let command = args.command(&config::Config::default()).unwrap();
+        let add_dirs: Vec<_> = command
+            .get_args()
+            .skip_while(|arg| *arg != "--add-dir")
+            .collect();
+        assert_eq!(add_dirs, ["--add-dir", "/a"]);
+    }
+
+    #[test]
+    fn labels_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let labels_file = dir.path().join("labels");
+        std::fs::write(
+            &labels_file,
+            "rlvkpnrz 7d3d2a1e Merge base\nfeature/login: Add OAuth support\nmain: Switch to sessions\n",
+        )
+        .unwrap();
+
+        let args = merge_args(&[
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "--labels-file",
+            labels_file.to_str().unwrap(),
+            "-y",
+            "upstream",
+        ]);
+        assert_eq!(
+            args.labels().unwrap(),
+            Labels {
+                ancestor: Some("rlvkpnrz 7d3d2a1e Merge base".to_owned()),
+                left: "feature/login: Add OAuth support".to_owned(),
+                // Flags win over the labels file.
+                right: "upstream".to_owned(),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_labels_file_partial() {
+        assert_eq!(
+            parse_labels_file("\nmy side\n"),
+            [None, Some("my side".to_owned()), None]
+        );
+    }
+
+    #[test]
+    fn read_events_logs_to_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = logging::MergeLogger::in_dir(dir.path(), Some("src/lib.rs"), "run");
+        let writer = claude_json::ClaudeEventWriter::new(claude_json::DisplayOptions {
+            plain: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let lines = [
+            r#"{"type":"system","subtype":"init"}"#,
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Merging."}]}}"#,
+            r#"{"type":"result","subtype":"success","is_error":false,"duration_ms":100,"duration_api_ms":90,"num_turns":1,"result":"ok","total_cost_usd":0.01,"usage":{"input_tokens":1,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":1},"modelUsage":{}}"#,
+        ];
+        let mut out = Vec::new();
+        let summary = read_events(
+            lines.iter().map(|line| Ok(line.to_string())),
+            &writer,
+            &mut logger,
+            &mut progress::ProgressReporter::disabled(),
+            &ProgressBar::hidden(),
+            &mut out,
+            None,
+        )
+        .unwrap();
+        drop(logger);
+
+        assert!(summary.saw_result);
+        assert_eq!(summary.result_text.as_deref(), Some("ok"));
+        assert_eq!(summary.total_cost_usd, Some(0.01));
+        assert_eq!(summary.error_reason, None);
+        assert!(String::from_utf8(out).unwrap().starts_with("Merging."));
+
+        let summary_log = std::fs::read_to_string(dir.path().join("summary.jsonl")).unwrap();
+        assert_eq!(summary_log.lines().count(), 1);
+        assert!(summary_log.contains(r#""run_id":"run""#), "{summary_log}");
+
+        let event_log = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.to_str().unwrap().ends_with("_src_lib.rs_run.jsonl"))
+            .unwrap();
+        let event_log = std::fs::read_to_string(event_log).unwrap();
+        assert_eq!(event_log.lines().collect::<Vec<_>>(), lines);
+    }
+
+    #[test]
+    fn read_events_result_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = logging::MergeLogger::in_dir(dir.path(), None, "run");
+        let writer = claude_json::ClaudeEventWriter::new(claude_json::DisplayOptions {
+            plain: true,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let lines = [
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Merging."}]}}"#,
+            r#"{"type":"result","subtype":"success","is_error":false,"duration_ms":100,"duration_api_ms":90,"num_turns":1,"result":"ok","total_cost_usd":0.01,"usage":{"input_tokens":1,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":1},"modelUsage":{}}"#,
+        ];
+        let mut out = Vec::new();
+        let mut result_out = Vec::new();
+        read_events(
+            lines.iter().map(|line| Ok(line.to_string())),
+            &writer,
+            &mut logger,
+            &mut progress::ProgressReporter::disabled(),
+            &ProgressBar::hidden(),
+            &mut out,
+            Some(&mut result_out),
+        )
+        .unwrap();
+
+        assert_eq!(String::from_utf8(out).unwrap(), "Merging.");
+        let result_out = String::from_utf8(result_out).unwrap();
+        assert!(
+            result_out.starts_with("Finished in 100ms"),
+            "{result_out:?}"
+        );
+        assert!(!result_out.contains("Merging."), "{result_out:?}");
+    }
+
+    #[test]
+    fn fail_on_web_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = logging::MergeLogger::in_dir(dir.path(), None, "run");
+        let writer = claude_json::ClaudeEventWriter::new(Default::default()).unwrap();
+        let line = r#"{"type":"result","subtype":"success","is_error":false,"duration_ms":100,"duration_api_ms":90,"num_turns":3,"result":"ok","total_cost_usd":0.05,"usage":{"input_tokens":1,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":1},"modelUsage":{"claude-opus-4-6":{"inputTokens":1,"outputTokens":1,"cacheReadInputTokens":0,"cacheCreationInputTokens":0,"webSearchRequests":2,"costUSD":0.05,"contextWindow":200000,"maxOutputTokens":32000}}}"#;
+        let summary = read_events(
+            std::iter::once(Ok(line.to_owned())),
+            &writer,
+            &mut logger,
+            &mut progress::ProgressReporter::disabled(),
+            &ProgressBar::hidden(),
+            std::io::sink(),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(summary.web_search_requests, 2);
+        let err = check_web_searches(summary.web_search_requests).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Claude made 2 web search request(s), which --fail-on-web-search forbids"
+        );
+        check_web_searches(0).unwrap();
+    }
+
+    #[test]
+    fn command_model_flag_overrides_config() {
+        let config: config::Config = toml::from_str(r#"model = "sonnet""#).unwrap();
+        let args = merge_args(&[
+            "--model",
+            "opus",
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+        ]);
+        let command = args.command(&config).unwrap();
+        let command_args: Vec<_> = command.get_args().collect();
+        let model = command_args
+            .iter()
+            .position(|arg| *arg == "--model")
+            .unwrap();
+        assert_eq!(command_args[model + 1], "opus");
+        assert_eq!(
+            command_args.iter().filter(|arg| **arg == "--model").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn command_model_flag_overrides_extra_args() {
+        let config: config::Config = toml::from_str(
+            r#"extra_args = ["--model", "sonnet", "--max-turns", "20", "--model=haiku"]"#,
+        )
+        .unwrap();
+        let command_args = |flags: &[&str]| {
+            let args = [
+                flags,
+                &[
+                    "/tmp/base.txt",
+                    "/tmp/left.txt",
+                    "/tmp/right.txt",
+                    "-o",
+                    "/tmp/output.txt",
+                ],
+            ]
+            .concat();
+            merge_args(&args)
+                .command(&config)
+                .unwrap()
+                .get_args()
+                .map(|arg| arg.to_str().unwrap().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let args = command_args(&["--model", "opus"]);
+        assert!(args.ends_with(&["--max-turns".to_owned(), "20".to_owned()]));
+        let models = args
+            .iter()
+            .enumerate()
+            .filter(|(_, arg)| arg.starts_with("--model"))
+            .map(|(i, _)| args[i + 1].as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(models, ["opus"]);
+
+        // Without `--model`, `extra_args` is passed through untouched.
+        let args = command_args(&[]);
+        assert!(args.ends_with(config.extra_args()));
+    }
+
+    #[test]
+    fn command_resume() {
+        let args = merge_args(&[
+            "--resume",
+            "5d2f6a10-8c1e-4b7a-9f3d-2e6c1a0b9d84",
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+        ]);
+        let command = args.command(&config::Config::default()).unwrap();
+        let command_args = command
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert!(
+            command_args
+                .windows(2)
+                .any(|pair| pair == ["--resume", "5d2f6a10-8c1e-4b7a-9f3d-2e6c1a0b9d84"]),
+            "{command_args:?}"
+        );
+
+        let config: config::Config = toml::from_str(
+            "[backend]\ntype = \"custom\"\nprogram = \"aider\"\nargs_template = []\n",
+        )
+        .unwrap();
+        assert!(args.command(&config).is_err());
+    }
+
+    #[test]
+    fn command_base_override() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("merge-base.txt");
+        std::fs::write(&base, "base\n").unwrap();
+
+        let args = merge_args(&[
+            "--base-override",
+            base.to_str().unwrap(),
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+            "-p",
+            "src/lib.rs",
+        ]);
+        let command = args.command(&config::Config::default()).unwrap();
+        let displayed = Utf8ProgramAndArgs::from(&command)
+            .to_string()
+            .replace(dir.path().to_str().unwrap(), "$OVERRIDE");
+        expect![[r#"
+            claude --print --verbose '--output-format=stream-json' '--permission-mode=acceptEdits' --append-system-prompt 'You are resolving a merge conflict in `src/lib.rs`. Your working directory is the root of the repository, so you can browse and edit other files if needed (e.g. if code moved between files).
+
+            Three versions of the file are provided as temporary files: the base (common ancestor), left (ours), and right (theirs). Read all three, understand what each side changed relative to the base, and write a resolved version to the output path. If changes are compatible, merge them cleanly. If they genuinely conflict, use your best judgment and explain your reasoning. If the conflict is so ambiguous that only a human can decide, leave the conflict markers in place and end your reply with a line starting with `UNRESOLVED:` explaining why.' 'Resolve the merge conflict in `src/lib.rs`.
+
+            Read these three versions of the file:
+            - Base (common ancestor): $OVERRIDE/merge-base.txt
+            - Left (ours): /tmp/left.txt
+            - Right (theirs): /tmp/right.txt
+
+            Write the resolved file to: /tmp/output.txt' --add-dir /tmp --add-dir $OVERRIDE"#]].assert_eq(&displayed);
+    }
+
+    #[test]
+    fn base_override_must_exist() {
+        let args = merge_args(&[
+            "--base-override",
+            "/nonexistent/merge-base.txt",
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+        ]);
+        let err = args.command(&config::Config::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Base override /nonexistent/merge-base.txt isn't a readable UTF-8 file"
+        );
+    }
+
+    #[test]
+    fn validate_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["base.txt", "left.txt", "right.txt"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let path = |name: &str| dir.path().join(name).display().to_string();
+        let output = dir.path().join("new/dir/output.txt");
+        let args = merge_args(&[
+            &path("base.txt"),
+            &path("left.txt"),
+            &path("right.txt"),
+            "-o",
+            &output.display().to_string(),
+        ]);
+        args.validate_paths().unwrap();
+        assert!(output.parent().unwrap().is_dir());
+
+        let args = merge_args(&[
+            &path("base.txt"),
+            &path("lfet.txt"),
+            &path("right.txt"),
+            "-o",
+            &path("output.txt"),
+        ]);
+        assert_eq!(
+            args.validate_paths().unwrap_err().to_string(),
+            format!(
+                "Left version {} doesn't exist or isn't readable",
+                path("lfet.txt")
+            )
+        );
+    }
+
+    #[test]
+    fn parse_three_way() {
+        let args = merge_args(&["base.txt", "left.txt", "right.txt", "-o", "out.txt"]);
+        assert_eq!(args.base, Path::new("base.txt"));
+        assert_eq!(args.left, Path::new("left.txt"));
+        assert_eq!(args.right, Path::new("right.txt"));
+        assert!(args.extra_sides.is_empty());
+    }
+
+    #[test]
+    fn parse_four_way() {
+        let args = merge_args(&[
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "/var/side3.txt",
+            "-o",
+            "/tmp/output.txt",
+        ]);
+        assert_eq!(args.extra_sides, [Path::new("/var/side3.txt")]);
+
+        let command = args.command(&config::Config::default()).unwrap();
+        let command_args: Vec<_> = command
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect();
+        let user_prompt = command_args
+            .iter()
+            .find(|arg| arg.starts_with("Resolve the merge conflict"))
+            .unwrap();
+        expect![[r#"
+            Resolve the merge conflict in `unknown file`.
+
+            Read these 4 versions of the file:
+            - Base (common ancestor): /tmp/base.txt
+            - Left (ours): /tmp/left.txt
+            - Right (theirs): /tmp/right.txt
+            - Side 3: /var/side3.txt
+
+            Write the resolved file to: /tmp/output.txt"#]]
+        .assert_eq(user_prompt);
+        assert!(command_args.ends_with(&["--add-dir", "/tmp", "--add-dir", "/var"]));
+
+        // The system prompt counts the versions the same way.
+        let system_prompt = command_args
+            .iter()
+            .skip_while(|arg| **arg != "--append-system-prompt")
+            .nth(1)
+            .unwrap();
+        assert!(
+            system_prompt.contains(
+                "4 versions of the file are provided as temporary files: the base (common \
+                 ancestor), left (ours), right (theirs), and side 3. Read all 4, understand"
+            ),
+            "{system_prompt}"
+        );
+    }
+
+    #[test]
+    fn substitute_placeholders_once() {
+        let values = [("left", "/tmp/{right}"), ("right", "/tmp/right.txt")];
+        assert_eq!(
+            substitute_placeholders("--file={left} {right} {other} {", &values),
+            "--file=/tmp/{right} /tmp/right.txt {other} {"
+        );
+    }
+
+    #[test]
+    fn command_custom_backend() {
+        let config: config::Config = toml::from_str(
+            r#"
+            [backend]
+            type = "custom"
+            program = "aider"
+            args_template = ["--yes", "--file", "{output}", "--read", "{base}", "--message", "{user_prompt}"]
+            "#,
+        )
+        .unwrap();
+        let args = merge_args(&[
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+            "-p",
+            "src/lib.rs",
+        ]);
+        let command = args.command(&config).unwrap();
+        expect![[r#"
+            aider --yes --file /tmp/output.txt --read /tmp/base.txt --message 'Resolve the merge conflict in `src/lib.rs`.
+
+            Read these three versions of the file:
+            - Base (common ancestor): /tmp/base.txt
+            - Left (ours): /tmp/left.txt
+            - Right (theirs): /tmp/right.txt
+
+            Write the resolved file to: /tmp/output.txt'"#]].assert_eq(&Utf8ProgramAndArgs::from(&command).to_string());
+    }
+
+    #[test]
+    fn command_two_way() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("base.txt");
+        std::fs::write(&base, "").unwrap();
+        assert!(is_missing_base(&base));
+        assert!(is_missing_base(Path::new("/dev/null")));
+
+        let args = merge_args(&[
+            "/dev/null",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+            "-p",
+            "src/new.rs",
+        ]);
+        let command = args.command(&config::Config::default()).unwrap();
+        let displayed: Utf8ProgramAndArgs = (&command).into();
+        expect![[r#"
+            claude --print --verbose '--output-format=stream-json' '--permission-mode=acceptEdits' --append-system-prompt 'You are resolving a merge conflict in `src/new.rs`. Your working directory is the root of the repository, so you can browse and edit other files if needed (e.g. if code moved between files).
+
+            Two versions of the file are provided as temporary files: left (ours) and right (theirs). There is no common ancestor because both sides added the file independently. Read both, understand what each side added, and write a resolved version to the output path. If changes are compatible, merge them cleanly. If they genuinely conflict, use your best judgment and explain your reasoning. If the conflict is so ambiguous that only a human can decide, leave the conflict markers in place and end your reply with a line starting with `UNRESOLVED:` explaining why.' 'Resolve the merge conflict in `src/new.rs`.
+
+            Read these two versions of the file:
+            - Left (ours): /tmp/left.txt
+            - Right (theirs): /tmp/right.txt
+
+            Write the resolved file to: /tmp/output.txt' --add-dir /tmp"#]].assert_eq(&displayed.to_string());
+    }
+
+    #[test]
+    fn command_strip_markers_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output.txt");
+        std::fs::write(&output, "a\n<<<<<<< ours\nb\n=======\nc\n>>>>>>> theirs\n").unwrap();
+
+        let args = merge_args(&[
+            "--strip-markers-first",
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            output.to_str().unwrap(),
+        ]);
+        let command = args.command(&config::Config::default()).unwrap();
+        let system_prompt = command
+            .get_args()
+            .skip_while(|arg| *arg != "--append-system-prompt")
+            .nth(1)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(
+            system_prompt.ends_with(
+                "with the conflict markers removed:\n\n\
+                 Conflict 1:\n<left>\nb\n</left>\n<right>\nc\n</right>\n"
+            ),
+            "{system_prompt}"
+        );
+    }
+
+    #[test]
+    fn unresolved_error_explains_the_merge_failed() {
+        let err = Command::new("false").status_checked().unwrap_err();
+        let err = unresolved_error(err);
+        let message = err.to_string();
+        assert!(
+            message.starts_with("The merge was not resolved: ")
+                && message.ends_with("exit status: 1"),
+            "{message}"
+        );
+        assert!(
+            err.help()
+                .unwrap()
+                .to_string()
+                .contains("conflict markers left in place")
+        );
+    }
+
+    #[test]
+    fn estimated_cost_from_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut logger = logging::MergeLogger::in_dir(dir.path(), None, "run");
+        let writer = claude_json::ClaudeEventWriter::new(Default::default()).unwrap();
+        let lines = [
+            // Claude repeats a message's usage for each of its content blocks.
+            r#"{"type":"assistant","message":{"id":"msg_1","model":"claude-sonnet-4-5","content":[{"type":"thinking","thinking":"Hmm."}],"usage":{"input_tokens":1000000,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":100000}}}"#,
+            r#"{"type":"assistant","message":{"id":"msg_1","model":"claude-sonnet-4-5","content":[{"type":"text","text":"Merging."}],"usage":{"input_tokens":1000000,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":100000}}}"#,
+            r#"{"type":"assistant","message":{"id":"msg_2","model":"my-finetune","content":[{"type":"text","text":"Done."}],"usage":{"input_tokens":1000000,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":0}}}"#,
+        ];
+        let summary = read_events(
+            lines.iter().map(|line| Ok(line.to_string())),
+            &writer,
+            &mut logger,
+            &mut progress::ProgressReporter::disabled(),
+            &ProgressBar::hidden(),
+            std::io::sink(),
+            None,
+        )
+        .unwrap();
+
+        // We don't know what `my-finetune` costs.
+        assert_eq!(summary.estimated_cost_usd(&BTreeMap::new()), None);
+
+        let pricing: BTreeMap<String, pricing::ModelPricing> = toml::from_str(
+            r#"
+            [my-finetune]
+            input = 2.0
+            output = 8.0
+            cache_read = 0.2
+            cache_write = 2.5
+            "#,
+        )
+        .unwrap();
+        let estimate = summary.estimated_cost_usd(&pricing).unwrap();
+        // $3 + $1.50 for Sonnet, and $2 for the finetune.
+        assert!((estimate - 6.5).abs() < 1e-9, "{estimate}");
+    }
+
+    #[test]
+    fn color_flag() {
+        let cli = Cli::try_parse_from(["claude-mergetool", "stats"]).unwrap();
+        assert_eq!(cli.color, ColorChoice::Auto);
+        // `--color` is global, so it can come after the subcommand.
+        let cli = Cli::try_parse_from(["claude-mergetool", "stats", "--color", "never"]).unwrap();
+        assert_eq!(cli.color, ColorChoice::Never);
+        assert!(!ColorChoice::Never.enabled());
+        assert!(ColorChoice::Always.enabled());
+        assert!(
+            Cli::try_parse_from(["claude-mergetool", "--color", "sometimes", "stats"]).is_err()
+        );
+
+        let cli = Cli::try_parse_from(["claude-mergetool", "stats", "--no-color"]).unwrap();
+        assert_eq!(cli.color(), ColorChoice::Never);
+        let cli = Cli::try_parse_from(["claude-mergetool", "stats", "--force-color"]).unwrap();
+        assert_eq!(cli.color(), ColorChoice::Always);
+        assert!(
+            Cli::try_parse_from([
+                "claude-mergetool",
+                "--force-color",
+                "--color",
+                "never",
+                "stats"
+            ])
+            .is_err()
+        );
+        assert!(
+            Cli::try_parse_from([
+                "claude-mergetool",
+                "--no-color",
+                "--color",
+                "always",
+                "stats"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn max_cost() {
+        let config: config::Config = toml::from_str("max_cost_usd = 0.5").unwrap();
+        let args = merge_args(&[
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+        ]);
+        let command = args.command(&config).unwrap();
+        let command_args: Vec<_> = command.get_args().collect();
+        let budget = command_args
+            .iter()
+            .position(|arg| *arg == "--max-budget-usd")
+            .unwrap();
+        assert_eq!(command_args[budget + 1], "0.5");
+
+        // `--max-cost` takes precedence.
+        let args = merge_args(&[
+            "--max-cost",
+            "2",
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+        ]);
+        assert_eq!(args.max_cost_usd(&config), Some(2.0));
+
+        check_cost(Some(0.25), 0.5).unwrap();
+        check_cost(None, 0.5).unwrap();
+        let err = check_cost(Some(0.75), 0.5).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Claude spent $0.7500, over the $0.5000 budget"
+        );
+    }
+
+    #[test]
+    fn command_tool_lists() {
+        let config: config::Config = toml::from_str(
+            r#"
+            allowed_tools = ["Read", "Edit", "Write"]
+            disallowed_tools = ["Bash", "WebFetch"]
+            "#,
+        )
+        .unwrap();
+        let args = merge_args(&[
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+        ]);
+        let command = args.command(&config).unwrap();
+        let command_args: Vec<_> = command.get_args().collect();
+        let allowed = command_args
+            .iter()
+            .position(|arg| *arg == "--allowedTools")
+            .unwrap();
+        assert_eq!(command_args[allowed + 1], "Read,Edit,Write");
+        let disallowed = command_args
+            .iter()
+            .position(|arg| *arg == "--disallowedTools")
+            .unwrap();
+        assert_eq!(command_args[disallowed + 1], "Bash,WebFetch");
+
+        let command = args.command(&config::Config::default()).unwrap();
+        assert!(
+            !command
+                .get_args()
+                .any(|arg| arg == "--allowedTools" || arg == "--disallowedTools")
+        );
+    }
+
+    #[test]
+    fn huge_lines() {
+        assert!(!has_huge_line("", 10));
+        assert!(!has_huge_line("short\nlines\n", 10));
+        assert!(!has_huge_line("exactly 10", 10));
+        assert!(has_huge_line("ok\nthis one is too long\n", 10));
+        // Counted in characters, not bytes.
+        assert!(!has_huge_line("ééééé", 5));
+        let minified = format!("function f(){{{}}}", "a=1;".repeat(10_000));
+        assert!(has_huge_line(&minified, 10_000));
+    }
+
+    #[test]
+    fn read_confirmation_answers() {
+        let answer = |input: &str| {
+            let mut out = Vec::new();
+            let answer = read_confirmation("Apply?", input.as_bytes(), &mut out).unwrap();
+            assert_eq!(String::from_utf8(out).unwrap(), "Apply? [y/N] ");
+            answer
+        };
+        assert!(answer("y\n"));
+        assert!(answer("YES\n"));
+        assert!(!answer("n\n"));
+        assert!(!answer("\n"));
+        // The terminal closed without an answer.
+        assert!(!answer(""));
+    }
+
+    #[test]
+    fn restore_output_after_abort() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output.txt");
+
+        std::fs::write(&output, "half-writ").unwrap();
+        restore_output(&output, Some(b"<<<<<<< ours\n"));
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "<<<<<<< ours\n");
+
+        restore_output(&output, None);
+        assert!(!output.exists());
+        // Nothing to delete is fine too.
+        restore_output(&output, None);
+    }
+
+    #[test]
+    fn output_permissions_flag() {
+        let args = merge_args(&[
+            "--output-permissions",
+            "0755",
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+        ]);
+        assert_eq!(args.output_permissions, Some(0o755));
+        assert_eq!(parse_mode("0o600"), Ok(0o600));
+        assert!(parse_mode("0999").is_err());
+        assert!(parse_mode("17777").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output_permissions_applied() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("hook.sh");
+        std::fs::write(&output, "#!/bin/sh\n").unwrap();
+        let args = merge_args(&[
+            "--output-permissions",
+            "0755",
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            output.to_str().unwrap(),
+        ]);
+        args.set_output_permissions().unwrap();
+        let mode = std::fs::metadata(&output).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o755);
+    }
+
+    #[test]
+    fn backup_output_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join(".merge_file_a1b2c3");
+
+        let backup = backup_output(&output, b"<<<<<<< ours\n").unwrap();
+        assert_eq!(backup, dir.path().join(".merge_file_a1b2c3.orig"));
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "<<<<<<< ours\n");
+
+        // An existing backup isn't overwritten.
+        let second = backup_output(&output, b"again\n").unwrap();
+        assert_ne!(second, backup);
+        let name = second.file_name().unwrap().to_str().unwrap();
+        assert!(
+            name.starts_with(".merge_file_a1b2c3.") && name.ends_with(".orig"),
+            "{name}"
+        );
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "<<<<<<< ours\n");
+        assert_eq!(std::fs::read_to_string(&second).unwrap(), "again\n");
+    }
+
+    #[test]
+    fn glob_matching() {
+        let matches = |pattern: &str, path: &str| glob_matches(pattern, Path::new(path));
+
+        // Without a `/`, only the file name is matched.
+        assert!(matches("Cargo.lock", "Cargo.lock"));
+        assert!(matches("Cargo.lock", "crates/foo/Cargo.lock"));
+        assert!(matches("*.lock", "./web/yarn.lock"));
+        assert!(matches("poetry.?ock", "poetry.lock"));
+        assert!(!matches("Cargo.lock", "Cargo.lock.orig"));
+        assert!(!matches("*.lock", "Cargo.toml"));
+
+        // With a `/`, the whole path is matched.
+        assert!(matches("web/*.json", "web/package-lock.json"));
+        assert!(!matches("web/*.json", "web/nested/package-lock.json"));
+        assert!(!matches("web/*.json", "package-lock.json"));
+        assert!(matches("vendor/**", "vendor/a/b/c.go"));
+        assert!(matches("**/flake.lock", "flake.lock"));
+        assert!(matches("**/flake.lock", "nix/flake.lock"));
+        assert!(!matches("**/flake.lock", "nix/notflake.lock"));
+        assert!(matches("docs/**/*.md", "docs/README.md"));
+        assert!(matches("docs/**/*.md", "docs/a/b/README.md"));
+        assert!(!matches("docs/**/*.md", "src/README.md"));
+    }
+
+    #[test]
+    fn regenerate_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let left = dir.path().join("left.lock");
+        let output = dir.path().join("uv.lock");
+        std::fs::write(&left, "ours\n").unwrap();
+        std::fs::write(&output, "<<<<<<< ours\n").unwrap();
+        let config: config::Config = toml::from_str(
+            r#"
+            [[regenerate]]
+            glob = "uv.lock"
+            command = ["sh", "-c", "echo regenerated >> uv.lock"]
+
+            [[regenerate]]
+            glob = "*.min.js"
+            "#,
+        )
+        .unwrap();
+        let args = |filepath: &str| {
+            merge_args(&[
+                "/tmp/base.txt",
+                left.to_str().unwrap(),
+                "/tmp/right.txt",
+                "-o",
+                output.to_str().unwrap(),
+                "-p",
+                filepath,
+            ])
+        };
+
+        assert!(matches!(
+            args(output.to_str().unwrap()).regenerate(&config),
+            Ok(Some(Outcome::Resolved))
+        ));
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "ours\nregenerated\n"
+        );
+
+        assert!(matches!(
+            args("dist/app.min.js").regenerate(&config),
+            Ok(Some(Outcome::Deferred))
+        ));
+        assert!(matches!(args("src/main.rs").regenerate(&config), Ok(None)));
+
+        std::fs::write(&output, "<<<<<<< ours\n").unwrap();
+        let mut dry_run = args(output.to_str().unwrap());
+        dry_run.dry_run_claude = true;
+        assert!(matches!(
+            dry_run.regenerate(&config),
+            Ok(Some(Outcome::DryRun))
+        ));
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "<<<<<<< ours\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn git_merge_driver_only_runs_claude_on_conflicts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+        let claude = dir.path().join("claude");
+        std::fs::write(
+            &claude,
+            format!(
+                r#"#!/bin/sh
+if [ "$1" = --version ]; then
+    echo "2.1.0 (Claude Code)"
+    exit 0
+fi
+echo run >> "{dir}/runs"
+printf 'resolved\n' > "{left}"
+echo '{{"type":"result","subtype":"success","is_error":false,"duration_ms":100,"duration_api_ms":90,"num_turns":1,"result":"Done.","total_cost_usd":0.25,"usage":{{"input_tokens":10,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":5}},"modelUsage":{{}}}}'
+"#,
+                dir = dir.path().display(),
+                left = path("left.txt"),
+            ),
+        )
+        .unwrap();
+        set_permissions(&claude, 0o755).unwrap();
+        std::fs::write(
+            dir.path().join("config.toml"),
+            format!(
+                "claude_binary = {:?}\nlog = false\n",
+                claude.to_str().unwrap()
+            ),
+        )
+        .unwrap();
+        let merge = |left: &str, right: &str| {
+            std::fs::write(dir.path().join("base.txt"), "a\nb\nc\n").unwrap();
+            std::fs::write(dir.path().join("left.txt"), left).unwrap();
+            std::fs::write(dir.path().join("right.txt"), right).unwrap();
+            let result = merge_args(&[
+                "--config",
+                &path("config.toml"),
+                "--git-merge-driver",
+                &path("base.txt"),
+                &path("left.txt"),
+                &path("right.txt"),
+            ])
+            .resolve_with_config_file(&mut webhook::MergeReport::default());
+            assert!(matches!(result, Ok(Outcome::Resolved)), "{result:?}");
+            std::fs::read_to_string(dir.path().join("left.txt")).unwrap()
+        };
+
+        // Changes Git can merge on its own don't cost anything.
+        assert_eq!(merge("A\nb\nc\n", "a\nb\nC\n"), "A\nb\nC\n");
+        assert!(!dir.path().join("runs").exists());
+
+        assert_eq!(merge("A\nb\nc\n", "Z\nb\nc\n"), "resolved\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("runs")).unwrap(),
+            "run\n"
+        );
+    }
+
+    #[test]
+    fn dry_run_claude_leaves_lockfile_conflicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+        for name in ["base.lock", "left.lock", "right.lock"] {
+            std::fs::write(dir.path().join(name), "ours\n").unwrap();
+        }
+        std::fs::write(dir.path().join("Cargo.lock"), "<<<<<<< ours\n").unwrap();
+        let outcome = merge_args(&[
+            "--resolve-binary-with-rules",
+            "--dry-run-claude",
+            &path("base.lock"),
+            &path("left.lock"),
+            &path("right.lock"),
+            "-o",
+            &path("Cargo.lock"),
+            "-p",
+            "Cargo.lock",
+        ])
+        .resolve_with_config_file(&mut webhook::MergeReport::default())
+        .unwrap();
+        assert!(matches!(outcome, Outcome::DryRun));
+        assert_eq!(outcome.exit_code(), ExitCode::FAILURE);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("Cargo.lock")).unwrap(),
+            "<<<<<<< ours\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn regenerated_files_post_to_webhook() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+        for name in ["base.lock", "left.lock", "right.lock"] {
+            std::fs::write(dir.path().join(name), "ours\n").unwrap();
+        }
+        std::fs::write(
+            dir.path().join("config.toml"),
+            "log = false\n[[regenerate]]\nglob = \"*.lock\"\ncommand = [\"true\"]\n",
+        )
+        .unwrap();
+        let run = |url: &str, flags: &[&str]| {
+            let mut args = vec![
+                "--config".to_owned(),
+                path("config.toml"),
+                "--result-webhook".to_owned(),
+                url.to_owned(),
+                path("base.lock"),
+                path("left.lock"),
+                path("right.lock"),
+                "-o".to_owned(),
+                path("app.lock"),
+                "-p".to_owned(),
+                path("app.lock"),
+            ];
+            args.extend(flags.iter().map(|flag| flag.to_string()));
+            let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+            merge_args(&args).run(false).unwrap()
+        };
+
+        // Regenerating returns before Claude would run, but is still reported.
+        let (url, server) = webhook::tests::mock_server(200);
+        assert_eq!(run(&url, &[]), ExitCode::SUCCESS);
+        let body: serde_json::Value = serde_json::from_str(&server.join().unwrap()).unwrap();
+        assert_eq!(body["filepath"], path("app.lock"));
+        assert_eq!(body["resolved"], true);
+
+        // Dry runs aren't.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/merges", listener.local_addr().unwrap());
+        run(&url, &["--dry-run"]);
+        run(&url, &["--dry-run-claude"]);
+        listener.set_nonblocking(true).unwrap();
+        assert!(listener.accept().is_err());
+    }
+
+    /// A fake `claude` which leaves conflict markers in `output` on its first run and resolves
+    /// them on later runs, saving each user prompt in `dir`.
+    #[cfg(unix)]
+    fn fake_claude(dir: &Path, output: &Path) -> PathBuf {
+        let script = dir.join("claude");
+        std::fs::write(
+            &script,
+            format!(
+                r#"#!/bin/sh
+if [ "$1" = --version ]; then
+    echo "2.1.0 (Claude Code)"
+    exit 0
+fi
+count=$(($(cat "{dir}/count" 2>/dev/null || echo 0) + 1))
+echo "$count" > "{dir}/count"
+for arg; do
+    case "$arg" in
+        "Resolve the merge conflict"*) printf '%s' "$arg" > "{dir}/prompt-$count" ;;
+    esac
+done
+if [ "$count" = 1 ]; then
+    printf 'a\n<<<<<<< ours\nb\n=======\nc\n>>>>>>> theirs\n' > "{output}"
+else
+    printf 'a\nb\nc\n' > "{output}"
+fi
+echo '{{"type":"result","subtype":"success","is_error":false,"duration_ms":100,"duration_api_ms":90,"num_turns":1,"result":"Done.","total_cost_usd":0.25,"usage":{{"input_tokens":10,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":5}},"modelUsage":{{}}}}'
+"#,
+                dir = dir.display(),
+                output = output.display(),
+            ),
+        )
+        .unwrap();
+        set_permissions(&script, 0o755).unwrap();
+        script
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn retry_on_markers() {
+        let run = |flags: &[&str]| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+            for name in ["base.txt", "left.txt", "right.txt"] {
+                std::fs::write(dir.path().join(name), "a\n").unwrap();
+            }
+            let output = dir.path().join("output.txt");
+            std::fs::write(&output, "<<<<<<< ours\n").unwrap();
+            let claude = fake_claude(dir.path(), &output);
+            let config = dir.path().join("config.toml");
+            std::fs::write(
+                &config,
+                format!(
+                    "claude_binary = {:?}\nlog = false\n",
+                    claude.to_str().unwrap()
+                ),
+            )
+            .unwrap();
+
+            let args = flags
+                .iter()
+                .map(|flag| flag.to_string())
+                .chain([
+                    "--config".to_owned(),
+                    path("config.toml"),
+                    path("base.txt"),
+                    path("left.txt"),
+                    path("right.txt"),
+                    "-o".to_owned(),
+                    path("output.txt"),
+                ])
+                .collect::<Vec<_>>();
+            let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+            let mut report = webhook::MergeReport::default();
+            let result = merge_args(&args).resolve_with_config_file(&mut report);
+            let count = std::fs::read_to_string(dir.path().join("count")).unwrap();
+            let retry_prompt = std::fs::read_to_string(dir.path().join("prompt-2")).ok();
+            (result, count.trim().to_owned(), retry_prompt, report, dir)
+        };
+
+        let (result, count, retry_prompt, report, dir) = run(&["--retry-on-markers"]);
+        assert!(matches!(result, Ok(Outcome::Resolved)), "{result:?}");
+        assert_eq!(count, "2");
+        let retry_prompt = retry_prompt.unwrap();
+        assert!(
+            retry_prompt.ends_with(
+                "Resolve the remaining conflicts, keeping the parts you've already resolved."
+            ),
+            "{retry_prompt}"
+        );
+        assert!(
+            retry_prompt.contains("still contains conflict markers (lines 2, 4, 6)"),
+            "{retry_prompt}"
+        );
+        assert_eq!(report.total_cost_usd, Some(0.5));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("output.txt")).unwrap(),
+            "a\nb\nc\n"
+        );
+
+        // `--retry-prompt` replaces what Claude is told.
+        let (result, _, retry_prompt, _, _) = run(&[
+            "--retry-on-markers",
+            "--retry-prompt",
+            "Try again ({kind}): {reason}",
+        ]);
+        assert!(matches!(result, Ok(Outcome::Resolved)), "{result:?}");
+        let retry_prompt = retry_prompt.unwrap();
+        assert!(
+            retry_prompt.contains("Try again (markers): ")
+                && retry_prompt.contains("still contains conflict markers (lines 2, 4, 6)"),
+            "{retry_prompt}"
+        );
+        assert!(
+            !retry_prompt.contains("keeping the parts"),
+            "{retry_prompt}"
+        );
+
+        // Without `--retry-on-markers`, Claude only runs once.
+        let (result, count, retry_prompt, _, _) = run(&[]);
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("still contains conflict markers"), "{err}");
+        assert_eq!(count, "1");
+        assert_eq!(retry_prompt, None);
+    }
+
+    #[test]
+    fn retry_reason_prompt() {
+        let validation =
+            RetryReason::Validation("the resolved file isn't valid JSON: EOF".to_owned());
+        assert_eq!(
+            validation.prompt(None),
+            "You already tried to resolve this conflict, but the resolved file isn't valid JSON: \
+             EOF. Fix the resolved file, keeping the parts you've already resolved."
+        );
+        assert_eq!(
+            validation.prompt(Some("{kind} failed: {reason}")),
+            "validation failed: the resolved file isn't valid JSON: EOF"
+        );
+        assert_eq!(
+            RetryReason::Markers("it has markers".to_owned()).prompt(Some("{kind} failed")),
+            "markers failed"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn interactive_guidance() {
+        let run = |claude_script: &str, answers: &str| {
+            let dir = tempfile::tempdir().unwrap();
+            let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+            for name in ["base.txt", "left.txt", "right.txt"] {
+                std::fs::write(dir.path().join(name), "a\n").unwrap();
+            }
+            std::fs::write(dir.path().join("output.txt"), "<<<<<<< ours\n").unwrap();
+            std::fs::write(dir.path().join("answers"), answers).unwrap();
+            let claude = dir.path().join("claude");
+            std::fs::write(
+                &claude,
+                format!(
+                    r#"#!/bin/sh
+if [ "$1" = --version ]; then
+    echo "2.1.0 (Claude Code)"
+    exit 0
+fi
+dir="{dir}"
+count=$(($(cat "$dir/count" 2>/dev/null || echo 0) + 1))
+echo "$count" > "$dir/count"
+for arg; do
+    case "$arg" in
+        "Resolve the merge conflict"*) printf '%s' "$arg" > "$dir/prompt-$count" ;;
+    esac
+done
+output=$(sed -n 's/^Write the resolved file to: //p' "$dir/prompt-$count")
+result() {{
+    echo '{{"type":"result","subtype":"success","is_error":false,"duration_ms":100,"duration_api_ms":90,"num_turns":1,"result":"'"$1"'","total_cost_usd":0.25,"usage":{{"input_tokens":10,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":5}},"modelUsage":{{}}}}'
+}}
+{claude_script}
+"#,
+                    dir = dir.path().display(),
+                ),
+            )
+            .unwrap();
+            set_permissions(&claude, 0o755).unwrap();
+            std::fs::write(
+                dir.path().join("config.toml"),
+                format!(
+                    "claude_binary = {:?}\nlog = false\n",
+                    claude.to_str().unwrap()
+                ),
+            )
+            .unwrap();
+
+            let mut args = merge_args(&[
+                "--interactive",
+                "--config",
+                &path("config.toml"),
+                &path("base.txt"),
+                &path("left.txt"),
+                &path("right.txt"),
+                "-o",
+                &path("output.txt"),
+            ]);
+            args.terminal = Some(dir.path().join("answers"));
+            let mut report = webhook::MergeReport::default();
+            let result = args.resolve_with_config_file(&mut report);
+            let count = std::fs::read_to_string(dir.path().join("count")).unwrap();
+            let prompt = std::fs::read_to_string(dir.path().join("prompt-2")).ok();
+            (result, count.trim().to_owned(), prompt, report, dir)
+        };
+
+        // Claude asks for a decision, then resolves the conflict with it.
+        let (result, count, prompt, report, dir) = run(
+            r#"if [ "$count" = 1 ]; then
+    result 'UNRESOLVED: Should the timeout be 5s or 10s?'
+else
+    printf 'a\n' > "$output"
+    result 'Done.'
+fi"#,
+            "Use 10s\ny\n",
+        );
+        assert!(matches!(result, Ok(Outcome::Resolved)), "{result:?}");
+        assert_eq!(count, "2");
+        let prompt = prompt.unwrap();
+        assert!(
+            prompt.contains(
+                "You left this conflict for a human to resolve: Should the timeout be 5s or \
+                 10s?\n\nThey replied: Use 10s"
+            ),
+            "{prompt}"
+        );
+        assert_eq!(report.total_cost_usd, Some(0.5));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("output.txt")).unwrap(),
+            "a\n"
+        );
+
+        // Rounds are capped.
+        let (result, count, _, report, _) = run(
+            "result 'UNRESOLVED: Which one?'",
+            "this one\nno, this one\nthat one\nthe other one\n",
+        );
+        assert!(matches!(result, Ok(Outcome::Deferred)), "{result:?}");
+        assert_eq!(count, (MAX_CLARIFICATIONS + 1).to_string());
+        assert_eq!(report.total_cost_usd, Some(1.0));
+
+        // Claude writes to a copy of the output, which is only applied once it's accepted.
+        let (result, _, _, _, dir) = run(
+            r#"printf 'a\n' > "$output"
+cp "$dir/output.txt" "$dir/output-while-running"
+echo "$output" > "$dir/claude-output"
+result 'Done.'"#,
+            "n\n",
+        );
+        assert!(matches!(result, Ok(Outcome::Rejected)), "{result:?}");
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("output-while-running"), "<<<<<<< ours\n");
+        assert_eq!(read("output.txt"), "<<<<<<< ours\n");
+        assert_ne!(
+            read("claude-output").trim(),
+            dir.path().join("output.txt").to_str().unwrap()
+        );
+
+        // A blank answer leaves the conflict for the user.
+        let (result, count, prompt, _, _) = run("result 'UNRESOLVED: Which one?'", "\n");
+        assert!(matches!(result, Ok(Outcome::Deferred)), "{result:?}");
+        assert_eq!(count, "1");
+        assert_eq!(prompt, None);
+    }
+
+    #[test]
+    fn retry_delays() {
+        let delays = (1..=8).map(retry_delay).collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            [1, 2, 4, 8, 16, 32, 60, 60].map(Duration::from_secs)
+        );
+        assert_eq!(retry_delay(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn retries_skip_lasting_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+        for name in ["base.txt", "left.txt", "right.txt", "output.txt"] {
+            std::fs::write(dir.path().join(name), "a\n").unwrap();
+        }
+        let failures = [
+            (
+                "max_turns",
+                r#"echo '{"type":"result","subtype":"error_max_turns","is_error":true,"duration_ms":100,"num_turns":20}'
+exit 1"#,
+            ),
+            ("interrupted", "kill -INT $$"),
+        ];
+        for (name, failure) in failures {
+            let claude = dir.path().join(name);
+            std::fs::write(
+                &claude,
+                format!(
+                    r#"#!/bin/sh
+if [ "$1" = --version ]; then
+    echo "2.1.0 (Claude Code)"
+    exit 0
+fi
+echo run >> "{dir}/{name}.runs"
+{failure}
+"#,
+                    dir = dir.path().display(),
+                ),
+            )
+            .unwrap();
+            set_permissions(&claude, 0o755).unwrap();
+            std::fs::write(
+                dir.path().join("config.toml"),
+                format!(
+                    "claude_binary = {:?}\nlog = false\nretries = 2\n",
+                    claude.to_str().unwrap()
+                ),
+            )
+            .unwrap();
+
+            let result = merge_args(&[
+                "--config",
+                &path("config.toml"),
+                &path("base.txt"),
+                &path("left.txt"),
+                &path("right.txt"),
+                "-o",
+                &path("output.txt"),
+            ])
+            .resolve_with_config_file(&mut webhook::MergeReport::default());
+            assert!(result.is_err(), "{name}: {result:?}");
+            let runs = std::fs::read_to_string(dir.path().join(format!("{name}.runs"))).unwrap();
+            assert_eq!(runs, "run\n", "{name}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn missing_result_is_a_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+        for name in ["base.txt", "left.txt", "right.txt"] {
+            std::fs::write(dir.path().join(name), "a\n").unwrap();
+        }
+        std::fs::write(dir.path().join("output.txt"), "<<<<<<< ours\n").unwrap();
+        // Exit successfully without a result event, having resolved the file.
+        let claude = dir.path().join("claude");
+        std::fs::write(
+            &claude,
+            format!(
+                r#"#!/bin/sh
+if [ "$1" = --version ]; then
+    echo "2.1.0 (Claude Code)"
+    exit 0
+fi
+echo run >> "{dir}/runs"
+printf 'a\n' > "{dir}/output.txt"
+"#,
+                dir = dir.path().display(),
+            ),
+        )
+        .unwrap();
+        set_permissions(&claude, 0o755).unwrap();
+        std::fs::write(
+            dir.path().join("config.toml"),
+            format!(
+                "claude_binary = {:?}\nlog = false\nretries = 1\n",
+                claude.to_str().unwrap()
+            ),
+        )
+        .unwrap();
+
+        let result = merge_args(&[
+            "--config",
+            &path("config.toml"),
+            &path("base.txt"),
+            &path("left.txt"),
+            &path("right.txt"),
+            "-o",
+            &path("output.txt"),
+        ])
+        .resolve_with_config_file(&mut webhook::MergeReport::default());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Claude exited without a result"
+        );
+        // It was retried, and the output was put back after each run.
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("runs")).unwrap(),
+            "run\nrun\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("output.txt")).unwrap(),
+            "<<<<<<< ours\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn retries_after_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+        for name in ["base.txt", "left.txt", "right.txt"] {
+            std::fs::write(dir.path().join(name), "a\n").unwrap();
+        }
+        std::fs::write(dir.path().join("output.txt"), "<<<<<<< ours\n").unwrap();
+        // Fail partway through writing the output on the first run, then succeed, saving the
+        // output each run started with.
+        let claude = dir.path().join("claude");
+        std::fs::write(
+            &claude,
+            format!(
+                r#"#!/bin/sh
+if [ "$1" = --version ]; then
+    echo "2.1.0 (Claude Code)"
+    exit 0
+fi
+count=$(($(cat "{dir}/count" 2>/dev/null || echo 0) + 1))
+echo "$count" > "{dir}/count"
+cp "{output}" "{dir}/start-$count"
+if [ "$count" = 1 ]; then
+    printf 'half-written' > "{output}"
+    exit 1
+fi
+printf 'a\n' > "{output}"
+echo '{{"type":"result","subtype":"success","is_error":false,"duration_ms":100,"duration_api_ms":90,"num_turns":1,"result":"Done.","total_cost_usd":0.25,"usage":{{"input_tokens":10,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":5}},"modelUsage":{{}}}}'
+"#,
+                dir = dir.path().display(),
+                output = path("output.txt"),
+            ),
+        )
+        .unwrap();
+        set_permissions(&claude, 0o755).unwrap();
+        std::fs::write(
+            dir.path().join("config.toml"),
+            format!(
+                "claude_binary = {:?}\nlog = false\nretries = 2\n",
+                claude.to_str().unwrap()
+            ),
+        )
+        .unwrap();
+
+        let result = merge_args(&[
+            "--config",
+            &path("config.toml"),
+            &path("base.txt"),
+            &path("left.txt"),
+            &path("right.txt"),
+            "-o",
+            &path("output.txt"),
+        ])
+        .resolve_with_config_file(&mut webhook::MergeReport::default());
+        assert!(matches!(result, Ok(Outcome::Resolved)), "{result:?}");
+        let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("count"), "2\n");
+        // The failed run's partial output was put back before the retry.
+        assert_eq!(read("start-2"), "<<<<<<< ours\n");
+        assert_eq!(read("output.txt"), "a\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn idle_timeout_kills_stalled_claude() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name).to_str().unwrap().to_owned();
+        for name in ["base.txt", "left.txt", "right.txt"] {
+            std::fs::write(dir.path().join(name), "a\n").unwrap();
+        }
+        std::fs::write(dir.path().join("output.txt"), "<<<<<<< ours\n").unwrap();
+        // Print one event, then hang without exiting.
+        let claude = dir.path().join("claude");
+        std::fs::write(
+            &claude,
+            format!(
+                r#"#!/bin/sh
+if [ "$1" = --version ]; then
+    echo "2.1.0 (Claude Code)"
+    exit 0
+fi
+echo '{{"type":"assistant","message":{{"content":[{{"type":"text","text":"Merging."}}]}}}}'
+printf 'half-written' > "{output}"
+exec sleep 30
+"#,
+                output = path("output.txt"),
+            ),
+        )
+        .unwrap();
+        set_permissions(&claude, 0o755).unwrap();
+        std::fs::write(
+            dir.path().join("config.toml"),
+            format!(
+                "claude_binary = {:?}\nlog = false\n",
+                claude.to_str().unwrap()
+            ),
+        )
+        .unwrap();
+
+        let start = std::time::Instant::now();
+        let result = merge_args(&[
+            "--idle-timeout",
+            "300ms",
+            "--timeout",
+            "1m",
+            "--config",
+            &path("config.toml"),
+            &path("base.txt"),
+            &path("left.txt"),
+            &path("right.txt"),
+            "-o",
+            &path("output.txt"),
+        ])
+        .resolve_with_config_file(&mut webhook::MergeReport::default());
+        let err = result.unwrap_err().to_string();
+        assert_eq!(err, "Claude produced no output for 300ms");
+        assert!(start.elapsed() < Duration::from_secs(20));
+        // The half-written output is put back.
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("output.txt")).unwrap(),
+            "<<<<<<< ours\n"
+        );
+    }
+
+    #[test]
+    fn command_renamed_file() {
+        let args = merge_args(&[
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+            "-p",
+            "src/utils.rs",
+            "--left-path",
+            "src/util.rs",
+        ]);
+        let command = args.command(&config::Config::default()).unwrap();
+        let displayed: Utf8ProgramAndArgs = (&command).into();
+        expect![[r#"
+            claude --print --verbose '--output-format=stream-json' '--permission-mode=acceptEdits' --append-system-prompt 'You are resolving a merge conflict in `src/utils.rs`. Your working directory is the root of the repository, so you can browse and edit other files if needed (e.g. if code moved between files).
+
+            Three versions of the file are provided as temporary files: the base (common ancestor), left (ours), and right (theirs). Read all three, understand what each side changed relative to the base, and write a resolved version to the output path. If changes are compatible, merge them cleanly. If they genuinely conflict, use your best judgment and explain your reasoning. If the conflict is so ambiguous that only a human can decide, leave the conflict markers in place and end your reply with a line starting with `UNRESOLVED:` explaining why.
+
+            The file was renamed: it'\''s `src/util.rs` on the left (ours) and `src/utils.rs` on the right (theirs). Apply each side'\''s changes to the merged file regardless of its name, and check whether other files refer to it by its old name.' 'Resolve the merge conflict in `src/utils.rs`.
+
+            Read these three versions of the file:
+            - Base (common ancestor): /tmp/base.txt
+            - Left (ours): /tmp/left.txt
+            - Right (theirs): /tmp/right.txt
+
+            Write the resolved file to: /tmp/output.txt' --add-dir /tmp"#]]
+        .assert_eq(&displayed.to_string());
+
+        // Same path on both sides: not a rename.
+        let args = merge_args(&[
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+            "--left-path",
+            "src/lib.rs",
+            "--right-path",
+            "src/lib.rs",
+        ]);
+        assert_eq!(args.renamed_paths(), None);
+    }
+
+    #[test]
+    fn command_dry_run_claude() {
+        let config: config::Config =
+            toml::from_str(r#"permission_mode = "bypassPermissions""#).unwrap();
+        let args = merge_args(&[
+            "--dry-run-claude",
+            "/tmp/base.txt",
+            "/tmp/left.txt",
+            "/tmp/right.txt",
+            "-o",
+            "/tmp/output.txt",
+        ]);
+        let command = args.command(&config).unwrap();
+        let command_args: Vec<_> = command
+            .get_args()
+            .map(|arg| arg.to_str().unwrap())
+            .collect();
+        assert!(command_args.contains(&"--permission-mode=plan"));
+        let system_prompt = command_args
+            .iter()
+            .position(|arg| *arg == "--append-system-prompt")
+            .unwrap();
+        assert!(
+            command_args[system_prompt + 1].ends_with(
+                "This is a dry run: don't write or edit any files. Instead, describe how you \
+                 would resolve the conflict, including the exact edits you would make."
+            ),
+            "{}",
+            command_args[system_prompt + 1]
+        );
+
+        let config: config::Config = toml::from_str(
+            r#"
+            [backend]
+            type = "custom"
+            program = "aider"
+            args_template = ["{user_prompt}"]
+            "#,
+        )
+        .unwrap();
+        assert!(args.command(&config).is_err());
+    }
 }
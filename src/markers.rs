@@ -0,0 +1,297 @@
+use miette::Context;
+use miette::IntoDiagnostic;
+use miette::miette;
+use std::path::Path;
+
+/// The conflict marker size Git and jj use unless told otherwise.
+pub const DEFAULT_MARKER_SIZE: u32 = 7;
+
+/// Claude starts a line of its final reply with this to leave the conflict for a human.
+pub const DEFERRAL_SENTINEL: &str = "UNRESOLVED:";
+
+/// Claude's explanation for leaving the conflict unresolved, if it chose to.
+pub fn deferral_reason(reply: &str) -> Option<&str> {
+    reply
+        .lines()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix(DEFERRAL_SENTINEL))
+        .map(str::trim)
+}
+
+/// Find lines in `contents` which look like conflict markers of the given size.
+///
+/// Returns 1-based line numbers.
+pub fn find_conflict_markers(contents: &str, marker_size: u32) -> Vec<usize> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| is_conflict_marker(line, marker_size))
+        .map(|(index, _)| index + 1)
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Marker {
+    /// `<<<<<<<`, before the left side.
+    Left,
+    /// `|||||||`, before the base in `diff3` style.
+    Base,
+    /// `=======`, before the right side.
+    Right,
+    /// `>>>>>>>`, after the right side.
+    End,
+}
+
+fn marker_kind(line: &str, marker_size: u32) -> Option<Marker> {
+    let marker_size = marker_size as usize;
+    [
+        ('<', Marker::Left),
+        ('|', Marker::Base),
+        ('=', Marker::Right),
+        ('>', Marker::End),
+    ]
+    .into_iter()
+    .find(|(c, marker)| {
+        let Some(rest) = line.strip_prefix(&c.to_string().repeat(marker_size)) else {
+            return false;
+        };
+        if *marker == Marker::Right {
+            // `=======` is never followed by a label.
+            rest.is_empty()
+        } else {
+            // Longer runs of the same character aren't markers of this size.
+            rest.is_empty() || rest.starts_with(' ')
+        }
+    })
+    .map(|(_, marker)| marker)
+}
+
+fn is_conflict_marker(line: &str, marker_size: u32) -> bool {
+    marker_kind(line, marker_size).is_some()
+}
+
+/// One conflicted region of a file with Git-style conflict markers.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Conflict {
+    pub left: String,
+    /// Only present with `diff3`-style markers.
+    pub base: Option<String>,
+    pub right: String,
+}
+
+/// Parse the conflicted regions out of a file with conflict markers, skipping any that aren't
+/// well-formed.
+pub fn parse_conflicts(contents: &str, marker_size: u32) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    // The conflict we're in, and which part of it we're reading.
+    let mut current: Option<(Conflict, Marker)> = None;
+
+    for line in contents.lines() {
+        match (marker_kind(line, marker_size), &mut current) {
+            (Some(Marker::Left), _) => {
+                current = Some((Conflict::default(), Marker::Left));
+            }
+            (Some(Marker::Base), Some((conflict, part @ Marker::Left))) => {
+                conflict.base = Some(String::new());
+                *part = Marker::Base;
+            }
+            (Some(Marker::Right), Some((_, part @ (Marker::Left | Marker::Base)))) => {
+                *part = Marker::Right;
+            }
+            (Some(Marker::End), Some((_, Marker::Right))) => {
+                if let Some((conflict, _)) = current.take() {
+                    conflicts.push(conflict);
+                }
+            }
+            (Some(_), _) => {
+                // Out of order; drop the malformed conflict.
+                current = None;
+            }
+            (None, Some((conflict, part))) => {
+                let side = match part {
+                    Marker::Left => &mut conflict.left,
+                    Marker::Base => conflict.base.get_or_insert_default(),
+                    Marker::Right | Marker::End => &mut conflict.right,
+                };
+                side.push_str(line);
+                side.push('\n');
+            }
+            (None, None) => {}
+        }
+    }
+
+    conflicts
+}
+
+/// Render the conflicted regions of `contents` with the markers stripped, each side in its own
+/// tags, for `--strip-markers-first`. Returns `None` if there are no conflicts.
+pub fn strip_markers(contents: &str, marker_size: u32) -> Option<String> {
+    let conflicts = parse_conflicts(contents, marker_size);
+    if conflicts.is_empty() {
+        return None;
+    }
+
+    let mut stripped = String::new();
+    for (i, conflict) in conflicts.iter().enumerate() {
+        if i > 0 {
+            stripped.push('\n');
+        }
+        stripped.push_str(&format!("Conflict {}:\n", i + 1));
+        stripped.push_str(&format!("<left>\n{}</left>\n", conflict.left));
+        if let Some(base) = &conflict.base {
+            stripped.push_str(&format!("<base>\n{base}</base>\n"));
+        }
+        stripped.push_str(&format!("<right>\n{}</right>\n", conflict.right));
+    }
+    Some(stripped)
+}
+
+/// Fail if the resolved file at `path` still contains conflict markers.
+pub fn check_file(path: &Path, marker_size: u32) -> miette::Result<()> {
+    let contents = std::fs::read_to_string(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let lines = find_conflict_markers(&contents, marker_size);
+    if lines.is_empty() {
+        return Ok(());
+    }
+
+    let lines = lines
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    Err(miette!(
+        "{} still contains conflict markers (lines {lines})",
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::expect;
+
+    #[test]
+    fn finds_markers() {
+        let contents = "a\n<<<<<<< ours\nb\n||||||| base\nc\n=======\nd\n>>>>>>> theirs\ne\n";
+        assert_eq!(
+            find_conflict_markers(contents, DEFAULT_MARKER_SIZE),
+            vec![2, 4, 6, 8]
+        );
+    }
+
+    #[test]
+    fn ignores_other_sizes_and_lookalikes() {
+        let contents = "<<<<<<<< ours\n========\n=======x\n  <<<<<<< indented\n<<<<<<<<\n";
+        assert!(find_conflict_markers(contents, DEFAULT_MARKER_SIZE).is_empty());
+        assert_eq!(find_conflict_markers(contents, 8), vec![1, 2, 5]);
+    }
+
+    #[test]
+    fn bare_markers() {
+        assert_eq!(find_conflict_markers("<<<<<<<\n>>>>>>>\n", 7), vec![1, 2]);
+    }
+
+    #[test]
+    fn parse_diff3_conflicts() {
+        let contents = "\
+fn main() {
+<<<<<<< ours
+    let total = add(1, 2);
+||||||| base
+    let sum = add(1, 2);
+=======
+    let sum = add(1, 2, 3);
+>>>>>>> theirs
+    println!(\"done\");
+<<<<<<< ours
+    cleanup();
+=======
+>>>>>>> theirs
+}
+";
+        assert_eq!(
+            parse_conflicts(contents, DEFAULT_MARKER_SIZE),
+            [
+                Conflict {
+                    left: "    let total = add(1, 2);\n".to_owned(),
+                    base: Some("    let sum = add(1, 2);\n".to_owned()),
+                    right: "    let sum = add(1, 2, 3);\n".to_owned(),
+                },
+                Conflict {
+                    left: "    cleanup();\n".to_owned(),
+                    base: None,
+                    right: String::new(),
+                },
+            ]
+        );
+
+        expect![[r#"
+            Conflict 1:
+            <left>
+                let total = add(1, 2);
+            </left>
+            <base>
+                let sum = add(1, 2);
+            </base>
+            <right>
+                let sum = add(1, 2, 3);
+            </right>
+
+            Conflict 2:
+            <left>
+                cleanup();
+            </left>
+            <right>
+            </right>
+        "#]]
+        .assert_eq(&strip_markers(contents, DEFAULT_MARKER_SIZE).unwrap());
+    }
+
+    #[test]
+    fn strip_markers_skips_malformed_conflicts() {
+        assert_eq!(
+            strip_markers("no conflicts here\n", DEFAULT_MARKER_SIZE),
+            None
+        );
+        // Missing the `=======` separator.
+        assert_eq!(
+            strip_markers("<<<<<<< ours\na\n>>>>>>> theirs\n", DEFAULT_MARKER_SIZE),
+            None
+        );
+    }
+
+    #[test]
+    fn deferral_sentinel() {
+        assert_eq!(
+            deferral_reason(
+                "I kept both conflict markers.\n\n\
+                 UNRESOLVED: both sides changed the retry limit for different reasons.\n"
+            ),
+            Some("both sides changed the retry limit for different reasons.")
+        );
+        assert_eq!(deferral_reason("Merged both changes cleanly."), None);
+        assert_eq!(
+            deferral_reason("The UNRESOLVED: marker isn't at the start."),
+            None
+        );
+    }
+
+    #[test]
+    fn check_file_reports_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("resolved.txt");
+
+        std::fs::write(&path, "resolved\n").unwrap();
+        check_file(&path, DEFAULT_MARKER_SIZE).unwrap();
+
+        std::fs::write(&path, "a\n<<<<<<< ours\nb\n=======\nc\n>>>>>>> theirs\n").unwrap();
+        let err = check_file(&path, DEFAULT_MARKER_SIZE).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("still contains conflict markers (lines 2, 4, 6)"),
+            "{err}"
+        );
+    }
+}
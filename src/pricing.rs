@@ -0,0 +1,199 @@
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// What a model costs, in US dollars per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPricing {
+    input: f64,
+    output: f64,
+    cache_read: f64,
+    cache_write: f64,
+}
+
+/// Tokens used by one model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub input: u64,
+    pub output: u64,
+    pub cache_read: u64,
+    pub cache_write: u64,
+}
+
+/// What a model's input tokens cost, split by how they were cached.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct InputCost {
+    /// Uncached input.
+    pub input: f64,
+    /// Input written to the prompt cache, which costs more than uncached input.
+    pub cache_write: f64,
+    /// Input read from the prompt cache, which costs much less than uncached input.
+    pub cache_read: f64,
+    /// What the same input would have cost without prompt caching.
+    pub uncached: f64,
+}
+
+impl InputCost {
+    pub fn total(&self) -> f64 {
+        self.input + self.cache_write + self.cache_read
+    }
+
+    /// How much prompt caching saved; negative if writing to the cache cost more than reading
+    /// from it saved.
+    pub fn caching_savings(&self) -> f64 {
+        self.uncached - self.total()
+    }
+}
+
+impl std::ops::AddAssign for InputCost {
+    fn add_assign(&mut self, other: Self) {
+        self.input += other.input;
+        self.cache_write += other.cache_write;
+        self.cache_read += other.cache_read;
+        self.uncached += other.uncached;
+    }
+}
+
+/// Rates for the current model families, matched against the model name when there's no
+/// configured rate for it. These go stale, so they're only used for estimates; the cost
+/// reported by `claude` itself always takes precedence.
+const DEFAULT_PRICING: &[(&str, ModelPricing)] = &[
+    (
+        "opus",
+        ModelPricing {
+            input: 5.0,
+            output: 25.0,
+            cache_read: 0.5,
+            cache_write: 6.25,
+        },
+    ),
+    (
+        "sonnet",
+        ModelPricing {
+            input: 3.0,
+            output: 15.0,
+            cache_read: 0.3,
+            cache_write: 3.75,
+        },
+    ),
+    (
+        "haiku",
+        ModelPricing {
+            input: 1.0,
+            output: 5.0,
+            cache_read: 0.1,
+            cache_write: 1.25,
+        },
+    ),
+];
+
+impl ModelPricing {
+    /// The configured rates for `model`, falling back to the bundled rates for its family.
+    pub fn lookup(configured: &BTreeMap<String, ModelPricing>, model: &str) -> Option<Self> {
+        if let Some(pricing) = configured.get(model) {
+            return Some(*pricing);
+        }
+        DEFAULT_PRICING
+            .iter()
+            .find(|(family, _)| model.contains(family))
+            .map(|(_, pricing)| *pricing)
+    }
+
+    pub fn input_cost_usd(&self, usage: &TokenUsage) -> InputCost {
+        const PER_TOKEN: f64 = 1.0 / 1_000_000.0;
+        InputCost {
+            input: PER_TOKEN * self.input * usage.input as f64,
+            cache_write: PER_TOKEN * self.cache_write * usage.cache_write as f64,
+            cache_read: PER_TOKEN * self.cache_read * usage.cache_read as f64,
+            uncached: PER_TOKEN
+                * self.input
+                * (usage.input + usage.cache_write + usage.cache_read) as f64,
+        }
+    }
+
+    pub fn cost_usd(&self, usage: &TokenUsage) -> f64 {
+        const PER_TOKEN: f64 = 1.0 / 1_000_000.0;
+        PER_TOKEN
+            * (self.input * usage.input as f64
+                + self.output * usage.output as f64
+                + self.cache_read * usage.cache_read as f64
+                + self.cache_write * usage.cache_write as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_configured_model() {
+        let custom = ModelPricing {
+            input: 2.0,
+            output: 10.0,
+            cache_read: 0.2,
+            cache_write: 2.5,
+        };
+        let configured = BTreeMap::from([("claude-sonnet-4-5".to_owned(), custom)]);
+
+        assert_eq!(
+            ModelPricing::lookup(&configured, "claude-sonnet-4-5"),
+            Some(custom)
+        );
+        // Other models use the bundled rates for their family.
+        assert_eq!(
+            ModelPricing::lookup(&configured, "claude-sonnet-4-6"),
+            Some(DEFAULT_PRICING[1].1)
+        );
+        assert_eq!(
+            ModelPricing::lookup(&configured, "claude-haiku-4-5"),
+            Some(DEFAULT_PRICING[2].1)
+        );
+        assert_eq!(ModelPricing::lookup(&configured, "gpt-5"), None);
+    }
+
+    #[test]
+    fn cost() {
+        let pricing = ModelPricing::lookup(&BTreeMap::new(), "claude-sonnet-4-5").unwrap();
+        let usage = TokenUsage {
+            input: 1_000_000,
+            output: 100_000,
+            cache_read: 2_000_000,
+            cache_write: 0,
+        };
+        assert!((pricing.cost_usd(&usage) - 5.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn input_cost() {
+        let pricing = ModelPricing::lookup(&BTreeMap::new(), "claude-sonnet-4-5").unwrap();
+        let close = |actual: f64, expected: f64| {
+            assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+        };
+
+        let cost = pricing.input_cost_usd(&TokenUsage {
+            input: 100_000,
+            output: 1_000_000,
+            cache_read: 2_000_000,
+            cache_write: 1_000_000,
+        });
+        close(cost.input, 0.3);
+        close(cost.cache_write, 3.75);
+        close(cost.cache_read, 0.6);
+        close(cost.total(), 4.65);
+        close(cost.uncached, 9.3);
+        close(cost.caching_savings(), 4.65);
+
+        // Writing to the cache without reading it back costs more than not caching.
+        let cost = pricing.input_cost_usd(&TokenUsage {
+            cache_write: 1_000_000,
+            ..Default::default()
+        });
+        close(cost.caching_savings(), -0.75);
+
+        let mut sum = cost;
+        sum += cost;
+        close(sum.cache_write, 7.5);
+        close(sum.caching_savings(), -1.5);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::claude_json::DisplayableEvent;
+use indicatif::ProgressBar;
+use serde::Serialize;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::time::Duration;
+
+/// A progress event for `--progress-json`.
+///
+/// Unlike Claude's own event stream, this is a small, stable schema meant for wrapper UIs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Progress {
+    /// The merge has started.
+    Started { file: String },
+    /// Claude has written its first text.
+    FirstToken,
+    /// Claude sent its `turn`th message, starting at 1.
+    Turn { turn: u64 },
+    /// Claude is writing or editing a file.
+    WritingFile { path: String },
+    /// The merge has finished.
+    Done { success: bool },
+}
+
+/// A spinner on stderr for the wait before Claude's first output, which can take several
+/// seconds. Hidden when stderr isn't a terminal, so it doesn't end up in logs.
+pub fn waiting_spinner() -> ProgressBar {
+    if !std::io::stderr().is_terminal() {
+        return ProgressBar::hidden();
+    }
+    let spinner = ProgressBar::new_spinner().with_message("Waiting for Claude...");
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+#[derive(Serialize)]
+struct ProgressLine<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    progress: &'a Progress,
+}
+
+/// Writes [`Progress`] events as JSON lines.
+pub struct ProgressReporter {
+    out: Option<Box<dyn Write>>,
+    turns: u64,
+    saw_text: bool,
+}
+
+impl ProgressReporter {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self {
+            out: Some(out),
+            turns: 0,
+            saw_text: false,
+        }
+    }
+
+    /// A reporter which doesn't write anything, for when `--progress-json` isn't given.
+    pub fn disabled() -> Self {
+        Self {
+            out: None,
+            turns: 0,
+            saw_text: false,
+        }
+    }
+
+    pub fn emit(&mut self, progress: &Progress) {
+        let Some(out) = &mut self.out else {
+            return;
+        };
+
+        let line = ProgressLine {
+            timestamp: jiff::Timestamp::now().to_string(),
+            progress,
+        };
+        let line = serde_json::to_string(&line).expect("progress events always serialize");
+        if let Err(e) = writeln!(out, "{line}").and_then(|()| out.flush()) {
+            tracing::warn!("Progress write failed, disabling: {e}");
+            self.out = None;
+        }
+    }
+
+    /// Emit progress for one of Claude's events.
+    pub fn observe(&mut self, event: &DisplayableEvent<'_>) {
+        for progress in self.progress_for(event) {
+            self.emit(&progress);
+        }
+    }
+
+    fn progress_for(&mut self, event: &DisplayableEvent<'_>) -> Vec<Progress> {
+        let mut progress = Vec::new();
+        if !event.is_assistant() {
+            return progress;
+        }
+
+        self.turns += 1;
+        progress.push(Progress::Turn { turn: self.turns });
+
+        if !self.saw_text && event.has_text() {
+            self.saw_text = true;
+            progress.push(Progress::FirstToken);
+        }
+
+        progress.extend(event.written_files().map(|path| Progress::WritingFile {
+            path: path.to_owned(),
+        }));
+
+        progress
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::claude_json::ClaudeEventWriter;
+
+    #[test]
+    fn progress_sequence() {
+        let writer = ClaudeEventWriter::new(Default::default()).unwrap();
+        let mut reporter = ProgressReporter::disabled();
+
+        let lines = [
+            r#"{"type":"system","subtype":"init"}"#,
+            r#"{"type":"assistant","message":{"content":[{"type":"tool_use","name":"Read","input":{"file_path":"/tmp/base.txt"}}]}}"#,
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Both sides renamed `add`."}]}}"#,
+            r#"{"type":"assistant","message":{"content":[{"type":"text","text":"Writing."},{"type":"tool_use","name":"Write","input":{"file_path":"/tmp/output.txt"}}]}}"#,
+            r#"{"type":"result","subtype":"success","is_error":false,"duration_ms":100,"duration_api_ms":90,"num_turns":3,"result":"ok","total_cost_usd":0.01,"usage":{"input_tokens":1,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":1},"modelUsage":{}}"#,
+        ];
+        let progress: Vec<_> = lines
+            .iter()
+            .filter_map(|line| writer.display(line))
+            .flat_map(|event| reporter.progress_for(&event))
+            .collect();
+
+        assert_eq!(
+            progress,
+            [
+                Progress::Turn { turn: 1 },
+                Progress::Turn { turn: 2 },
+                Progress::FirstToken,
+                Progress::Turn { turn: 3 },
+                Progress::WritingFile {
+                    path: "/tmp/output.txt".to_owned()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn progress_line_format() {
+        let line = serde_json::to_value(ProgressLine {
+            timestamp: "2026-01-02T03:04:05Z".to_owned(),
+            progress: &Progress::Started {
+                file: "src/lib.rs".to_owned(),
+            },
+        })
+        .unwrap();
+        assert_eq!(
+            line,
+            serde_json::json!({
+                "timestamp": "2026-01-02T03:04:05Z",
+                "event": "started",
+                "file": "src/lib.rs",
+            })
+        );
+    }
+}
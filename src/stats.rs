@@ -0,0 +1,173 @@
+use crate::claude_json::Dollars;
+use crate::claude_json::HumanTime;
+use crate::claude_json::Tokens;
+use crate::claude_json::parse_result_usage;
+use jiff::Timestamp;
+use jiff::civil::Date;
+use miette::Context;
+use miette::IntoDiagnostic;
+use miette::miette;
+use serde::Deserialize;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(clap::Args, Debug)]
+pub struct StatsArgs {
+    /// Only count merges on or after this date (e.g. `2026-01-31`), in the local timezone.
+    #[arg(long, value_name = "DATE")]
+    since: Option<Date>,
+
+    /// The summary log to read. Defaults to `summary.jsonl` in the log directory.
+    #[arg(long, value_name = "PATH")]
+    summary: Option<PathBuf>,
+}
+
+impl StatsArgs {
+    pub fn run(self) -> miette::Result<()> {
+        let path = match self.summary {
+            Some(path) => path,
+            None => crate::logging::summary_path()
+                .ok_or_else(|| miette!("Couldn't determine the log directory"))?,
+        };
+        let since = self
+            .since
+            .map(|date| date.to_zoned(jiff::tz::TimeZone::system()))
+            .transpose()
+            .into_diagnostic()?
+            .map(|zoned| zoned.timestamp());
+
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err)
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("Failed to read {}", path.display()));
+            }
+        };
+
+        println!("{}", Stats::from_summary(&contents, since));
+        Ok(())
+    }
+}
+
+/// The fields of a summary log line that aren't part of Claude's result event.
+#[derive(Deserialize)]
+struct SummaryRunInfo {
+    timestamp: Option<String>,
+}
+
+/// Totals over the successful merges in the summary log.
+#[derive(Debug, Default, PartialEq)]
+struct Stats {
+    merges: u64,
+    total_cost_usd: f64,
+    tokens: u64,
+    duration: Duration,
+}
+
+impl Stats {
+    /// Aggregate the lines of `summary.jsonl`, skipping failed merges and lines we can't parse.
+    ///
+    /// With `since`, lines logged before timestamps were recorded are skipped too.
+    fn from_summary(contents: &str, since: Option<Timestamp>) -> Self {
+        let mut stats = Self::default();
+        for line in contents.lines() {
+            if let Some(since) = since {
+                let timestamp = serde_json::from_str::<SummaryRunInfo>(line)
+                    .ok()
+                    .and_then(|info| info.timestamp?.parse::<Timestamp>().ok());
+                if timestamp.is_none_or(|timestamp| timestamp < since) {
+                    continue;
+                }
+            }
+
+            let Some(usage) = parse_result_usage(line) else {
+                continue;
+            };
+            stats.merges += 1;
+            stats.total_cost_usd += usage.total_cost_usd;
+            stats.tokens += usage.tokens;
+            stats.duration += usage.duration;
+        }
+        stats
+    }
+
+    fn average_duration(&self) -> Duration {
+        match u32::try_from(self.merges) {
+            Ok(0) | Err(_) => Duration::ZERO,
+            Ok(merges) => self.duration / merges,
+        }
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Merges: {}", self.merges)?;
+        writeln!(f, "Total cost: {}", Dollars(self.total_cost_usd))?;
+        writeln!(f, "Total tokens: {}", Tokens(self.tokens))?;
+        write!(
+            f,
+            "Average duration: {}",
+            HumanTime(self.average_duration())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expect_test::expect;
+
+    fn result_line(timestamp: &str, cost: f64, duration_ms: u64) -> String {
+        format!(
+            r#"{{"type":"result","subtype":"success","is_error":false,"duration_ms":{duration_ms},"duration_api_ms":90,"num_turns":3,"result":"ok","total_cost_usd":{cost},"usage":{{"input_tokens":100,"cache_creation_input_tokens":20,"cache_read_input_tokens":30,"output_tokens":50}},"modelUsage":{{}},"run_id":"01ABC","timestamp":"{timestamp}"}}"#
+        )
+    }
+
+    #[test]
+    fn aggregate_summary() {
+        let contents = [
+            result_line("2026-01-01T12:00:00Z", 0.25, 2000),
+            r#"{"type":"result","subtype":"error_max_turns","is_error":true,"duration_ms":100,"num_turns":30}"#.to_owned(),
+            "not json".to_owned(),
+            result_line("2026-02-01T12:00:00Z", 0.5, 4000),
+        ]
+        .join("\n");
+
+        let stats = Stats::from_summary(&contents, None);
+        assert_eq!(stats.merges, 2);
+        assert_eq!(stats.tokens, 400);
+        expect![[r#"
+            Merges: 2
+            Total cost: $0.7500
+            Total tokens: 400
+            Average duration: 3.00s"#]]
+        .assert_eq(&stats.to_string());
+
+        let since = "2026-01-15T00:00:00Z".parse().unwrap();
+        let stats = Stats::from_summary(&contents, Some(since));
+        assert_eq!(stats.merges, 1);
+        assert_eq!(stats.total_cost_usd, 0.5);
+    }
+
+    #[test]
+    fn since_skips_lines_without_timestamps() {
+        let line = result_line("2026-01-01T12:00:00Z", 0.25, 2000)
+            .replace(r#","timestamp":"2026-01-01T12:00:00Z""#, "");
+        assert_eq!(Stats::from_summary(&line, None).merges, 1);
+        let since = "2000-01-01T00:00:00Z".parse().unwrap();
+        assert_eq!(Stats::from_summary(&line, Some(since)).merges, 0);
+    }
+
+    #[test]
+    fn empty_summary() {
+        expect![[r#"
+            Merges: 0
+            Total cost: $0.0000
+            Total tokens: 0
+            Average duration: 0ms"#]]
+        .assert_eq(&Stats::from_summary("", None).to_string());
+    }
+}
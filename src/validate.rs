@@ -0,0 +1,232 @@
+use clap::ValueEnum;
+use miette::miette;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+
+/// A structured data format that a resolved file can be checked against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Json => write!(f, "JSON"),
+            Format::Yaml => write!(f, "YAML"),
+            Format::Toml => write!(f, "TOML"),
+        }
+    }
+}
+
+impl Format {
+    /// Check that `contents` parses as this format, returning the parser's error message if not.
+    pub fn validate(&self, contents: &str) -> Result<(), String> {
+        match self {
+            Format::Json => serde_json::from_str::<serde_json::Value>(contents)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            Format::Yaml => {
+                // YAML files may contain multiple documents; check each of them.
+                for document in serde_yaml::Deserializer::from_str(contents) {
+                    serde_yaml::Value::deserialize(document).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            }
+            Format::Toml => contents
+                .parse::<toml::Table>()
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Parse an `EXT=FORMAT` pair for `--validate-extension`.
+pub fn parse_extension_mapping(s: &str) -> Result<(String, Format), String> {
+    let (extension, format) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected `EXT=FORMAT`, got `{s}`"))?;
+    let extension = extension.trim_start_matches('.');
+    if extension.is_empty() {
+        return Err(format!("missing extension in `{s}`"));
+    }
+    let format = Format::from_str(format, true)?;
+    Ok((extension.to_ascii_lowercase(), format))
+}
+
+/// Maps file extensions to the format their contents must parse as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validators {
+    by_extension: BTreeMap<String, Format>,
+}
+
+impl Default for Validators {
+    fn default() -> Self {
+        Self {
+            by_extension: [
+                ("json", Format::Json),
+                ("yaml", Format::Yaml),
+                ("yml", Format::Yaml),
+                ("toml", Format::Toml),
+            ]
+            .into_iter()
+            .map(|(extension, format)| (extension.to_owned(), format))
+            .collect(),
+        }
+    }
+}
+
+impl Validators {
+    /// The default mapping, with `overrides` added on top.
+    pub fn with_overrides<'a>(overrides: impl IntoIterator<Item = &'a (String, Format)>) -> Self {
+        let mut validators = Self::default();
+        for (extension, format) in overrides {
+            validators.by_extension.insert(extension.clone(), *format);
+        }
+        validators
+    }
+
+    /// The format files at `path` are expected to parse as, if any.
+    pub fn for_path(&self, path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        self.by_extension.get(&extension).copied()
+    }
+
+    /// Check the file at `path`, using `filepath` (the original path in the repository) to pick
+    /// the format.
+    pub fn validate_file(&self, filepath: &Path, path: &Path) -> miette::Result<()> {
+        match self.check_file(filepath, path)? {
+            Some((format, e)) => Err(miette!(
+                "Resolved `{}` is not valid {format}, leaving the conflict unresolved: {e}",
+                filepath.display()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Like [`Validators::validate_file`], but returning the format the file should be and the
+    /// parser's error message if it's invalid.
+    pub fn check_file(
+        &self,
+        filepath: &Path,
+        path: &Path,
+    ) -> miette::Result<Option<(Format, String)>> {
+        let Some(format) = self.for_path(filepath) else {
+            tracing::debug!("No validator for {}", filepath.display());
+            return Ok(None);
+        };
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| miette!("Failed to read resolved file {}: {e}", path.display()))?;
+
+        Ok(format.validate(&contents).err().map(|e| (format, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_valid() {
+        assert_eq!(Format::Json.validate(r#"{"a": [1, 2, 3]}"#), Ok(()));
+    }
+
+    #[test]
+    fn json_invalid() {
+        // Trailing commas are a classic.
+        assert!(Format::Json.validate(r#"{"a": [1, 2, 3,]}"#).is_err());
+    }
+
+    #[test]
+    fn toml_valid() {
+        assert_eq!(
+            Format::Toml.validate("[package]\nname = \"foo\"\nversion = \"1.0.0\"\n"),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn toml_invalid() {
+        // Duplicate keys, as produced by naively keeping both sides.
+        assert!(
+            Format::Toml
+                .validate("[package]\nversion = \"1.0.0\"\nversion = \"1.1.0\"\n")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn yaml_multiple_documents() {
+        assert_eq!(Format::Yaml.validate("a: 1\n---\nb: 2\n"), Ok(()));
+        assert!(Format::Yaml.validate("a: 1\n---\nb: [2\n").is_err());
+    }
+
+    #[test]
+    fn conflict_markers_are_invalid() {
+        let contents = "{\n<<<<<<< ours\n  \"a\": 1\n=======\n  \"a\": 2\n>>>>>>> theirs\n}\n";
+        assert!(Format::Json.validate(contents).is_err());
+    }
+
+    #[test]
+    fn validators_default_mapping() {
+        let validators = Validators::default();
+        assert_eq!(
+            validators.for_path(Path::new("package.json")),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            validators.for_path(Path::new(".github/ci.YML")),
+            Some(Format::Yaml)
+        );
+        assert_eq!(validators.for_path(Path::new("src/lib.rs")), None);
+        assert_eq!(validators.for_path(Path::new("Makefile")), None);
+    }
+
+    #[test]
+    fn validators_overrides() {
+        let overrides = vec![
+            parse_extension_mapping("lock=toml").unwrap(),
+            parse_extension_mapping(".json=yaml").unwrap(),
+        ];
+        let validators = Validators::with_overrides(&overrides);
+        assert_eq!(
+            validators.for_path(Path::new("Cargo.lock")),
+            Some(Format::Toml)
+        );
+        assert_eq!(
+            validators.for_path(Path::new("package.json")),
+            Some(Format::Yaml)
+        );
+    }
+
+    #[test]
+    fn parse_extension_mapping_errors() {
+        assert!(parse_extension_mapping("json").is_err());
+        assert!(parse_extension_mapping("=json").is_err());
+        assert!(parse_extension_mapping("json=xml").is_err());
+    }
+
+    #[test]
+    fn validate_file_rejects_invalid_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("output");
+        std::fs::write(&output, "{\"a\": 1,}").unwrap();
+        let validators = Validators::default();
+        assert!(
+            validators
+                .validate_file(Path::new("config.json"), &output)
+                .is_err()
+        );
+        // No validator for `.rs`, so anything goes.
+        assert!(
+            validators
+                .validate_file(Path::new("src/lib.rs"), &output)
+                .is_ok()
+        );
+    }
+}
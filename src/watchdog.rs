@@ -0,0 +1,154 @@
+use std::io::BufRead;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Why [`ChildLines`] stopped reading early.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timeout {
+    /// The total timeout passed.
+    Total(Duration),
+    /// No line arrived within the idle timeout.
+    Idle(Duration),
+}
+
+/// Lines read from a child process's output, which stop at a deadline, or when the child goes
+/// quiet for too long, even if the child is still running.
+///
+/// Reading blocks, so the lines are read on a background thread and handed over through a
+/// channel we can wait on with a timeout.
+pub struct ChildLines {
+    lines: Receiver<std::io::Result<String>>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    idle_timeout: Option<Duration>,
+    timed_out: Option<Timeout>,
+}
+
+impl ChildLines {
+    pub fn new(reader: impl BufRead + Send + 'static, timeout: Option<Duration>) -> Self {
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in reader.lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            lines,
+            timeout,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            idle_timeout: None,
+            timed_out: None,
+        }
+    }
+
+    /// Also stop reading if no line arrives within `idle_timeout` of the previous one.
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Which timeout stopped us reading, if any.
+    pub fn timed_out(&self) -> Option<Timeout> {
+        self.timed_out
+    }
+}
+
+impl Iterator for ChildLines {
+    type Item = std::io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.timed_out.is_some() {
+            return None;
+        }
+
+        let total = self.timeout.zip(self.deadline).map(|(timeout, deadline)| {
+            (
+                deadline.saturating_duration_since(Instant::now()),
+                Timeout::Total(timeout),
+            )
+        });
+        let idle = self.idle_timeout.map(|idle| (idle, Timeout::Idle(idle)));
+        let Some((wait, timeout)) = total.into_iter().chain(idle).min_by_key(|(wait, _)| *wait)
+        else {
+            return self.lines.recv().ok();
+        };
+
+        match self.lines.recv_timeout(wait) {
+            Ok(line) => Some(line),
+            Err(RecvTimeoutError::Timeout) => {
+                self.timed_out = Some(timeout);
+                None
+            }
+            Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+    use std::process::Command;
+    use std::process::Stdio;
+
+    #[test]
+    fn stops_at_deadline() {
+        let mut child = Command::new("sh")
+            .args(["-c", "echo started; sleep 30"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+
+        let start = Instant::now();
+        let mut lines = ChildLines::new(stdout, Some(Duration::from_millis(200)));
+        assert_eq!(lines.next().unwrap().unwrap(), "started");
+        assert!(lines.next().is_none());
+        assert_eq!(
+            lines.timed_out(),
+            Some(Timeout::Total(Duration::from_millis(200)))
+        );
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn idle_timeout_resets_on_each_line() {
+        let mut child = Command::new("sh")
+            .args([
+                "-c",
+                "for i in 1 2 3 4 5; do echo $i; sleep 0.1; done; exec sleep 30",
+            ])
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+
+        let idle = Duration::from_millis(400);
+        let mut lines =
+            ChildLines::new(stdout, Some(Duration::from_secs(60))).with_idle_timeout(Some(idle));
+        let read = lines.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read, ["1", "2", "3", "4", "5"]);
+        assert_eq!(lines.timed_out(), Some(Timeout::Idle(idle)));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn no_timeout_reads_everything() {
+        let mut lines = ChildLines::new(&b"one\ntwo\n"[..], None);
+        assert_eq!(lines.next().unwrap().unwrap(), "one");
+        assert_eq!(lines.next().unwrap().unwrap(), "two");
+        assert!(lines.next().is_none());
+        assert_eq!(lines.timed_out(), None);
+    }
+}
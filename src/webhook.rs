@@ -0,0 +1,118 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+/// How long to wait for the webhook before giving up, so a slow dashboard doesn't hold up the
+/// merge.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The summary of a merge posted to `result_webhook`.
+#[derive(Debug, Default, Serialize)]
+pub struct MergeReport {
+    pub filepath: String,
+    pub resolved: bool,
+    pub total_cost_usd: Option<f64>,
+    pub duration_ms: u128,
+    /// Files Claude wrote or edited.
+    pub edited_files: BTreeSet<String>,
+}
+
+/// POST `report` as JSON to `url`, warning if it fails.
+pub fn post(url: &str, report: &MergeReport) {
+    let body = serde_json::to_string(report).expect("merge reports always serialize");
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .build()
+        .new_agent();
+    match agent
+        .post(url)
+        .header("Content-Type", "application/json")
+        .send(body.as_str())
+    {
+        Ok(_) => tracing::debug!("Posted merge result to {url}"),
+        Err(err) => tracing::warn!("Failed to post merge result to {url}: {err}"),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::io::BufReader;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    /// Accept one request, reply with `status`, and return the request body.
+    pub fn mock_server(status: u16) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/merges", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 {status} Whatever\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )
+            .unwrap();
+            String::from_utf8(body).unwrap()
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn posts_report() {
+        let (url, server) = mock_server(200);
+        post(
+            &url,
+            &MergeReport {
+                filepath: "src/lib.rs".to_owned(),
+                resolved: true,
+                total_cost_usd: Some(0.25),
+                duration_ms: 1500,
+                edited_files: BTreeSet::from(["/tmp/output.rs".to_owned()]),
+            },
+        );
+
+        let body: serde_json::Value = serde_json::from_str(&server.join().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "filepath": "src/lib.rs",
+                "resolved": true,
+                "total_cost_usd": 0.25,
+                "duration_ms": 1500,
+                "edited_files": ["/tmp/output.rs"],
+            })
+        );
+    }
+
+    #[test]
+    fn failures_are_not_fatal() {
+        let (url, server) = mock_server(500);
+        post(&url, &MergeReport::default());
+        server.join().unwrap();
+
+        // Nothing listening.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/merges", listener.local_addr().unwrap());
+        drop(listener);
+        post(&url, &MergeReport::default());
+    }
+}
@@ -160,3 +160,251 @@ fn resolve_merge_conflict() {
         "resolved file is missing `Welcome` from the right side:\n{resolved}"
     );
 }
+
+/// A successful result event from a fake `claude`, with `result` as Claude's final reply.
+#[cfg(unix)]
+fn result_event(result: &str) -> String {
+    format!(
+        r#"{{"type":"result","subtype":"success","is_error":false,"duration_ms":100,"duration_api_ms":90,"num_turns":1,"result":"{result}","total_cost_usd":0.25,"usage":{{"input_tokens":10,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":5}},"modelUsage":{{}}}}"#
+    )
+}
+
+/// Write the three versions of a conflicted file and a config which runs `script` as `claude`,
+/// returning the arguments for `claude-mergetool merge`.
+#[cfg(unix)]
+fn fake_merge(dir: &std::path::Path, script: &str) -> Vec<String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+    for name in ["base.txt", "left.txt", "right.txt"] {
+        fs::write(dir.join(name), "a\n").unwrap();
+    }
+    fs::write(
+        dir.join("output.txt"),
+        "<<<<<<< ours\na\n=======\nb\n>>>>>>> theirs\n",
+    )
+    .unwrap();
+    let claude = dir.join("claude");
+    fs::write(
+        &claude,
+        format!(
+            "#!/bin/sh\nif [ \"$1\" = --version ]; then\n    echo '2.1.0 (Claude Code)'\n    exit 0\nfi\n{script}\n"
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&claude, fs::Permissions::from_mode(0o755)).unwrap();
+    fs::write(
+        dir.join("config.toml"),
+        format!("claude_binary = {:?}\nlog = false\n", path("claude")),
+    )
+    .unwrap();
+    vec![
+        "merge".to_owned(),
+        "--config".to_owned(),
+        path("config.toml"),
+        path("base.txt"),
+        path("left.txt"),
+        path("right.txt"),
+        "-o".to_owned(),
+        path("output.txt"),
+    ]
+}
+
+#[cfg(unix)]
+#[test]
+fn json_output_is_printed_once() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    // Leave the markers on the first run, so `--retry-on-markers` runs Claude again.
+    let script = format!(
+        r#"if [ -e "{dir}/ran" ]; then
+    printf 'a\n' > "{dir}/output.txt"
+    echo '{second}'
+else
+    touch "{dir}/ran"
+    echo '{first}'
+fi"#,
+        dir = dir.display(),
+        first = result_event("first"),
+        second = result_event("second"),
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_claude-mergetool"))
+        .args(fake_merge(dir, &script))
+        .args([
+            "--retry-on-markers",
+            "--output-format",
+            "json",
+            "-p",
+            "src/lib.rs",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    // Running Claude again doesn't announce the merge again.
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr
+            .matches("Resolving merge conflict in src/lib.rs")
+            .count(),
+        1,
+        "{stderr}"
+    );
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1, "{stdout}");
+    let json: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(json["result"], "second");
+
+    // A merge that fails a check afterwards doesn't print a result.
+    let output = Command::new(env!("CARGO_BIN_EXE_claude-mergetool"))
+        .args(fake_merge(dir, &format!("echo '{}'", result_event("done"))))
+        .args(["--output-format", "json", "--max-cost", "0.1"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success(), "{output:?}");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "");
+}
+
+/// Run a fake merge with logging on, with the logs going to `dir/state`, returning the merge's
+/// output and the lines of the summary log.
+#[cfg(unix)]
+fn logged_fake_merge(
+    dir: &std::path::Path,
+    script: &str,
+    args: &[&str],
+) -> (std::process::Output, Vec<serde_json::Value>) {
+    let merge_args = fake_merge(dir, script);
+    fs::write(
+        dir.join("config.toml"),
+        format!(
+            "claude_binary = {:?}\nlog = true\n",
+            dir.join("claude").to_str().unwrap()
+        ),
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_claude-mergetool"))
+        .args(merge_args)
+        .args(args)
+        .env("XDG_STATE_HOME", dir.join("state"))
+        .output()
+        .unwrap();
+    let summary =
+        fs::read_to_string(dir.join("state/claude-mergetool/logs/summary.jsonl")).unwrap();
+    let summary = summary
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    (output, summary)
+}
+
+#[cfg(unix)]
+#[test]
+fn interrupted_merge_is_logged() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    // Ctrl-C reaches the whole process group; send it to `claude-mergetool` and stop like
+    // `claude` would. Whether or not the handler has run by the time `claude` exits, its exit
+    // status says it was interrupted.
+    let script = format!(
+        r#"printf 'half\n' > "{}/output.txt"
+kill -INT $PPID
+exit 130"#,
+        dir.display()
+    );
+    let (output, summary) = logged_fake_merge(dir, &script, &[]);
+    assert!(!output.status.success(), "{output:?}");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Interrupted"),
+        "{output:?}"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("output.txt")).unwrap(),
+        "<<<<<<< ours\na\n=======\nb\n>>>>>>> theirs\n"
+    );
+    assert_eq!(summary.len(), 1, "{summary:?}");
+    assert_eq!(summary[0]["type"], "abort");
+    assert_eq!(summary[0]["reason"], "interrupted");
+}
+
+#[cfg(unix)]
+#[test]
+fn error_result_restores_output() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    // `claude` reports an error but exits 0.
+    let script = format!(
+        r#"printf 'half\n' > "{}/output.txt"
+echo '{{"type":"result","subtype":"error_max_turns","is_error":true,"duration_ms":100,"num_turns":20}}'"#,
+        dir.display()
+    );
+    let (output, summary) = logged_fake_merge(dir, &script, &[]);
+    assert!(!output.status.success(), "{output:?}");
+    assert_eq!(
+        fs::read_to_string(dir.join("output.txt")).unwrap(),
+        "<<<<<<< ours\na\n=======\nb\n>>>>>>> theirs\n"
+    );
+    let abort = summary.last().unwrap();
+    assert_eq!(abort["type"], "abort", "{summary:?}");
+    assert_eq!(
+        abort["reason"],
+        "Claude failed: reached the maximum number of turns"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn over_budget_merge_is_logged() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let script = format!(
+        r#"printf 'a\n' > "{}/output.txt"
+echo '{}'"#,
+        dir.display(),
+        result_event("done")
+    );
+    let (output, summary) = logged_fake_merge(dir, &script, &["--max-cost", "0.1"]);
+    assert!(!output.status.success(), "{output:?}");
+    let abort = summary.last().unwrap();
+    assert_eq!(abort["type"], "abort", "{summary:?}");
+    assert!(
+        abort["reason"].as_str().unwrap().contains("0.1"),
+        "{summary:?}"
+    );
+    assert_eq!(abort["total_cost_usd"], 0.25);
+}
+
+#[cfg(unix)]
+#[test]
+fn timeout_kills_claude() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let script = format!(
+        r#"echo $$ > "{dir}/pid"
+printf 'half-written' > "{dir}/output.txt"
+exec sleep 30"#,
+        dir = dir.display()
+    );
+    let start = std::time::Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_claude-mergetool"))
+        .args(fake_merge(dir, &script))
+        .args(["--timeout", "500ms"])
+        .output()
+        .unwrap();
+    assert!(start.elapsed() < std::time::Duration::from_secs(20));
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Claude timed out after 500ms"), "{stderr}");
+
+    // The half-written output is put back.
+    assert_eq!(
+        fs::read_to_string(dir.join("output.txt")).unwrap(),
+        "<<<<<<< ours\na\n=======\nb\n>>>>>>> theirs\n"
+    );
+    // And `claude` is gone.
+    let pid = fs::read_to_string(dir.join("pid")).unwrap();
+    let alive = Command::new("kill")
+        .args(["-0", pid.trim()])
+        .status()
+        .unwrap();
+    assert!(!alive.success(), "claude is still running as {pid}");
+}